@@ -0,0 +1,328 @@
+//! Remembering the banned users of channels, to post bans and unbans that happened while the bot was down, see `--bans-file`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::WrapErr;
+use tokio::sync::{Mutex, Notify};
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::{helix, types, HelixClient};
+
+use crate::event::{Ban, ModerationEvent, User};
+
+/// A banned user.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BannedUser {
+    pub user: User,
+    pub ban: Ban,
+}
+
+/// The users banned in `channel` by user id, from Helix `get_banned_users`. Timeouts are left out.
+pub async fn get_banned_users(
+    client: &HelixClient<'static, reqwest::Client>,
+    token: &Mutex<UserToken>,
+    channel: &types::UserIdRef,
+) -> Result<BTreeMap<String, BannedUser>, eyre::Report> {
+    let token = token.lock().await;
+    let mut banned = BTreeMap::new();
+    let mut response = Some(
+        client
+            .req_get(
+                helix::moderation::GetBannedUsersRequest::broadcaster_id(channel),
+                &*token,
+            )
+            .await
+            .wrap_err("could not get banned users")?,
+    );
+    while let Some(page) = response {
+        for ban in &page.data {
+            if ban.expires_at.is_some() {
+                continue;
+            }
+            banned.insert(
+                ban.user_id.as_str().to_owned(),
+                BannedUser {
+                    user: User::new(&ban.user_id, &ban.user_login, &ban.user_name),
+                    ban: Ban {
+                        created_at: ban.created_at.as_str().to_owned(),
+                        expires_at: None,
+                        moderator: User::new(
+                            &ban.moderator_id,
+                            &ban.moderator_login,
+                            &ban.moderator_name,
+                        ),
+                        reason: ban.reason.clone(),
+                    },
+                },
+            );
+        }
+        response = page
+            .get_next(client, &*token)
+            .await
+            .wrap_err("could not get banned users")?;
+    }
+    Ok(banned)
+}
+
+/// How long to wait for more changes before writing the file, so a ban wave is written once
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// The banned users of channels by channel id, kept in a file.
+///
+/// Changes are written by [`Bans::persist`] in the background, call [`Bans::save`] to write what is left when stopping.
+pub struct Bans {
+    path: PathBuf,
+    snapshot: std::sync::Mutex<Snapshot>,
+    /// Notified when the snapshot changes
+    changed: Notify,
+    /// Version of the snapshot in the file, locked while writing so an older snapshot can't replace a newer one
+    written: Arc<std::sync::Mutex<u64>>,
+}
+
+struct Snapshot {
+    /// Bumped on every change
+    version: u64,
+    channels: HashMap<String, BTreeMap<String, BannedUser>>,
+}
+
+impl Bans {
+    /// Read the snapshot in `path`. A missing or broken file is an empty snapshot.
+    pub fn load(path: PathBuf) -> Self {
+        let channels = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!(error = ?e, path = %path.display(), "could not parse bans file");
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                tracing::warn!(error = ?e, path = %path.display(), "could not read bans file");
+                HashMap::new()
+            }
+        };
+        Self {
+            path,
+            snapshot: std::sync::Mutex::new(Snapshot {
+                version: 0,
+                channels,
+            }),
+            changed: Notify::new(),
+            written: Default::default(),
+        }
+    }
+
+    /// Change the snapshot with `f`, and have it written if `f` returns `true`.
+    fn update(&self, f: impl FnOnce(&mut HashMap<String, BTreeMap<String, BannedUser>>) -> bool) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if f(&mut snapshot.channels) {
+            snapshot.version += 1;
+            self.changed.notify_one();
+        }
+    }
+
+    /// Replace the banned users of `channel` with `banned`, returning `ban` and `unban` events for the
+    /// differences to the snapshot. A channel that isn't in the snapshot yet gets no events.
+    pub fn catch_up(
+        &self,
+        channel: &User,
+        banned: BTreeMap<String, BannedUser>,
+        now: &types::TimestampRef,
+    ) -> Vec<ModerationEvent> {
+        let mut previous = None;
+        self.update(|channels| {
+            previous = channels.insert(channel.id.clone(), banned.clone());
+            true
+        });
+        let Some(previous) = previous else {
+            return vec![];
+        };
+        let mut events = vec![];
+        for (id, banned_user) in &banned {
+            if previous.contains_key(id) {
+                continue;
+            }
+            let Ok(created_at) = types::Timestamp::new(banned_user.ban.created_at.clone()) else {
+                continue;
+            };
+            let mut event = ModerationEvent::new(
+                "ban",
+                &created_at,
+                channel.clone(),
+                Some(banned_user.ban.moderator.clone()),
+            );
+            event.target = Some(banned_user.user.clone());
+            if !banned_user.ban.reason.is_empty() {
+                event.reason = Some(banned_user.ban.reason.clone());
+            }
+            event.ban = Some(banned_user.ban.clone());
+            event.detected_on_reconnect = true;
+            events.push(event);
+        }
+        for (id, banned_user) in previous {
+            if banned.contains_key(&id) {
+                continue;
+            }
+            // twitch doesn't say who lifted the ban, or when
+            let mut event = ModerationEvent::new("unban", now, channel.clone(), None);
+            event.target = Some(banned_user.user);
+            event.ban = Some(banned_user.ban);
+            event.detected_on_reconnect = true;
+            events.push(event);
+        }
+        events
+    }
+
+    /// Forget the banned users of a channel that is no longer monitored.
+    pub fn remove(&self, channel_id: &str) {
+        self.update(|channels| channels.remove(channel_id).is_some());
+    }
+
+    /// Keep the snapshot up to date with `ban` and `unban` actions.
+    pub fn observe(&self, event: &ModerationEvent) {
+        let Some(ref target) = event.target else {
+            return;
+        };
+        let ban = match (event.kind.as_str(), &event.moderator) {
+            ("ban", Some(moderator)) => Some(Ban {
+                created_at: event.timestamp.clone(),
+                expires_at: None,
+                moderator: moderator.clone(),
+                reason: event.reason.clone().unwrap_or_default(),
+            }),
+            ("unban", _) => None,
+            _ => return,
+        };
+        self.update(|channels| {
+            let Some(banned) = channels.get_mut(&event.channel.id) else {
+                return false;
+            };
+            match ban {
+                Some(ban) => {
+                    banned.insert(
+                        target.id.clone(),
+                        BannedUser {
+                            user: target.clone(),
+                            ban,
+                        },
+                    );
+                }
+                None => {
+                    banned.remove(&target.id);
+                }
+            }
+            true
+        });
+    }
+
+    /// Write the snapshot whenever it changes, waiting [`SAVE_DELAY`] for more changes first. Runs until dropped.
+    pub async fn persist(&self) {
+        loop {
+            self.changed.notified().await;
+            tokio::time::sleep(SAVE_DELAY).await;
+            self.save().await;
+        }
+    }
+
+    /// Write the snapshot to the file on a blocking thread, if it changed since it was last written.
+    pub async fn save(&self) {
+        let (version, content) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            if snapshot.version == *self.written.lock().unwrap() {
+                return;
+            }
+            (snapshot.version, serde_json::to_string(&snapshot.channels))
+        };
+        let path = self.path.clone();
+        let written = self.written.clone();
+        let result = match content {
+            Ok(content) => tokio::task::spawn_blocking(move || {
+                let mut written = written.lock().unwrap();
+                if *written >= version {
+                    return Ok(());
+                }
+                write_file(&path, content)?;
+                *written = version;
+                Ok(())
+            })
+            .await
+            .map_err(eyre::Report::from)
+            .and_then(|r| r),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            tracing::warn!(error = ?e, path = %self.path.display(), "could not write bans file");
+        }
+    }
+}
+
+/// Replace `path` with `content`, through a temporary file so a crash can't leave it half written.
+fn write_file(path: &Path, content: String) -> Result<(), eyre::Report> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    fn banned(id: &str, login: &str) -> (String, BannedUser) {
+        (
+            id.to_owned(),
+            BannedUser {
+                user: user(id, login),
+                ban: Ban {
+                    created_at: "2024-01-01T00:00:00Z".to_owned(),
+                    expires_at: None,
+                    moderator: user("2", "justintv"),
+                    reason: "spam".to_owned(),
+                },
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn posts_missed_bans_and_unbans() {
+        let path = std::env::temp_dir().join(format!("bans-{}.json", std::process::id()));
+        let now = types::Timestamp::new("2024-01-02T00:00:00Z".to_owned()).unwrap();
+        let channel = user("1", "twitch");
+
+        let bans = Bans::load(path.clone());
+        let first = [banned("3", "spam_bot"), banned("4", "other_bot")];
+        assert!(bans.catch_up(&channel, first.into(), &now).is_empty());
+        bans.save().await;
+
+        // banned while running
+        let mut ban =
+            ModerationEvent::new("ban", &now, channel.clone(), Some(user("2", "justintv")));
+        ban.target = Some(user("5", "troll"));
+        bans.observe(&ban);
+        // written in the background
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("troll"));
+        bans.save().await;
+
+        let bans = Bans::load(path.clone());
+        let current = [
+            banned("3", "spam_bot"),
+            banned("5", "troll"),
+            banned("6", "new_bot"),
+        ];
+        let events = bans.catch_up(&channel, current.into(), &now);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.kind.as_str(), e.target.as_ref().unwrap().login.as_str()))
+                .collect::<Vec<_>>(),
+            [("ban", "new_bot"), ("unban", "other_bot")]
+        );
+        assert!(events.iter().all(|e| e.detected_on_reconnect));
+        assert_eq!(events[0].timestamp, "2024-01-01T00:00:00Z");
+        assert_eq!(events[0].moderator.as_ref().unwrap().login, "justintv");
+        assert!(events[1].moderator.is_none());
+    }
+}
@@ -0,0 +1,198 @@
+//! Recent chat messages of users, see `--chat-context`.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use twitch_api::types;
+
+use crate::event::{ChatMessage, ModerationEvent, CHATTER_ACTIONS};
+
+/// Chatters to remember at most. When there are more, the one that was seen first is forgotten.
+const MAX_CHATTERS: usize = 10_000;
+
+/// The last messages of every chatter, by channel and chatter.
+pub struct ChatHistory {
+    per_user: usize,
+    chatters: Mutex<Chatters>,
+}
+
+#[derive(Default)]
+struct Chatters {
+    messages: HashMap<(types::UserId, types::UserId), VecDeque<ChatMessage>>,
+    /// Keys of `messages`, in the order they were added
+    order: VecDeque<(types::UserId, types::UserId)>,
+}
+
+impl ChatHistory {
+    /// Remember the last `per_user` messages of every chatter.
+    pub fn new(per_user: usize) -> Self {
+        Self {
+            per_user,
+            chatters: Default::default(),
+        }
+    }
+
+    pub fn push(
+        &self,
+        channel: &types::UserIdRef,
+        chatter: &types::UserIdRef,
+        message: ChatMessage,
+    ) {
+        if self.per_user == 0 {
+            return;
+        }
+        let mut chatters = self.chatters.lock().unwrap();
+        let Chatters { messages, order } = &mut *chatters;
+        let key = (channel.to_owned(), chatter.to_owned());
+        if !messages.contains_key(&key) {
+            if order.len() >= MAX_CHATTERS {
+                if let Some(oldest) = order.pop_front() {
+                    messages.remove(&oldest);
+                }
+            }
+            order.push_back(key.clone());
+        }
+        let messages = messages.entry(key).or_default();
+        if messages.len() >= self.per_user {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    /// Recent messages of a chatter in a channel, oldest first.
+    pub fn recent(
+        &self,
+        channel: &types::UserIdRef,
+        chatter: &types::UserIdRef,
+    ) -> Vec<ChatMessage> {
+        self.chatters
+            .lock()
+            .unwrap()
+            .messages
+            .get(&(channel.to_owned(), chatter.to_owned()))
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Set [`ModerationEvent::chat_context`] on bans, timeouts, warnings and deletes.
+    pub fn enrich(&self, event: &mut ModerationEvent) {
+        let Some(ref target) = event.target else {
+            return;
+        };
+        if !CHATTER_ACTIONS.contains(&event.kind.as_str()) {
+            return;
+        }
+        event.chat_context = self.recent(
+            types::UserIdRef::from_str(&event.channel.id),
+            types::UserIdRef::from_str(&target.id),
+        );
+    }
+}
+
+/// Users that were warned and haven't acknowledged it yet, by channel and user.
+#[derive(Default)]
+pub struct PendingWarnings(Mutex<HashSet<(String, String)>>);
+
+impl PendingWarnings {
+    /// Keep track of `warn` and `warning_acknowledge` events.
+    pub fn update(&self, event: &ModerationEvent) {
+        let Some(ref target) = event.target else {
+            return;
+        };
+        let key = (event.channel.id.clone(), target.id.clone());
+        match event.kind.as_str() {
+            "warn" => {
+                self.0.lock().unwrap().insert(key);
+            }
+            "warning_acknowledge" => {
+                self.0.lock().unwrap().remove(&key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a user chatted with a pending warning. Only the first message after a warning counts.
+    pub fn chatted(&self, channel: &types::UserIdRef, chatter: &types::UserIdRef) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .remove(&(channel.as_str().to_owned(), chatter.as_str().to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(i: usize) -> ChatMessage {
+        ChatMessage {
+            timestamp: format!("2024-01-01T00:00:{i:02}Z"),
+            message_id: i.to_string(),
+            text: format!("message {i}"),
+        }
+    }
+
+    #[test]
+    fn keeps_the_last_messages() {
+        let history = ChatHistory::new(3);
+        let channel = types::UserIdRef::from_static("1");
+        let chatter = types::UserIdRef::from_static("2");
+        for i in 0..5 {
+            history.push(channel, chatter, message(i));
+        }
+        history.push(channel, types::UserIdRef::from_static("3"), message(5));
+        assert_eq!(
+            history.recent(channel, chatter),
+            [message(2), message(3), message(4)]
+        );
+        assert!(history
+            .recent(types::UserIdRef::from_static("4"), chatter)
+            .is_empty());
+    }
+
+    #[test]
+    fn forgets_the_first_chatters() {
+        let history = ChatHistory::new(1);
+        let channel = types::UserIdRef::from_static("1");
+        for i in 0..=MAX_CHATTERS {
+            let chatter = i.to_string();
+            history.push(channel, types::UserIdRef::from_str(&chatter), message(0));
+        }
+        assert!(history
+            .recent(channel, types::UserIdRef::from_static("0"))
+            .is_empty());
+        assert_eq!(
+            history.recent(channel, types::UserIdRef::from_static("1")),
+            [message(0)]
+        );
+    }
+
+    #[test]
+    fn keeps_nothing_without_context() {
+        let history = ChatHistory::new(0);
+        let channel = types::UserIdRef::from_static("1");
+        let chatter = types::UserIdRef::from_static("2");
+        history.push(channel, chatter, message(0));
+        assert!(history.recent(channel, chatter).is_empty());
+    }
+
+    #[test]
+    fn flags_the_first_message_after_a_warning() {
+        let user = |id: &str| crate::event::user(id, id);
+        let timestamp = types::Timestamp::new("2024-01-01T00:00:00Z".to_owned()).unwrap();
+        let mut event = ModerationEvent::new("warn", &timestamp, user("1"), Some(user("3")));
+        event.target = Some(user("2"));
+        let channel = types::UserIdRef::from_static("1");
+        let chatter = types::UserIdRef::from_static("2");
+
+        let warnings = PendingWarnings::default();
+        warnings.update(&event);
+        assert!(warnings.chatted(channel, chatter));
+        assert!(!warnings.chatted(channel, chatter));
+
+        warnings.update(&event);
+        event.kind = "warning_acknowledge".to_owned();
+        warnings.update(&event);
+        assert!(!warnings.chatted(channel, chatter));
+    }
+}
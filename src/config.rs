@@ -0,0 +1,344 @@
+//! Configuration file, see `--config`
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+
+/// Settings read from a TOML or YAML file.
+///
+/// Top level keys that are not one of the sections below are commandline options by their long name, e.g
+///
+/// ```toml
+/// discord-webhook = "https://discord.com/api/webhooks/111111111111/aaaaaaaaaaaaaaa"
+/// channel-login = "justintv"
+/// channels = ["twitch", "twitchdev"]
+/// ```
+#[derive(Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// Logins of channels to monitor, in addition to `--channel-login` or `--channel-id`.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Where to send events, see [`crate::routing::Route`]. Events that match no route go to `--discord-webhook`.
+    #[serde(default)]
+    pub routes: Vec<crate::routing::Route>,
+    /// Filters for noisy events, see [`crate::filter::Filter`]. Applied before routing.
+    #[serde(default)]
+    pub filters: Vec<crate::filter::Filter>,
+    /// Other places to send events to, see [`crate::sink::SinkConfig`]. Filters and routes only apply to discord.
+    #[serde(default)]
+    pub sinks: Vec<crate::sink::SinkConfig>,
+    /// Commandline options
+    #[serde(flatten)]
+    options: BTreeMap<String, serde_json::Value>,
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // options can contain secrets, only show which are set
+        f.debug_struct("Config")
+            .field("channels", &self.channels)
+            .field("routes", &self.routes)
+            .field("filters", &self.filters)
+            .field("sinks", &self.sinks)
+            .field("options", &self.options.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read config file `{}`", path.display()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)
+                .wrap_err_with(|| format!("could not parse config file `{}`", path.display())),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)
+                .wrap_err_with(|| format!("could not parse config file `{}`", path.display())),
+            _ => eyre::bail!(
+                "unknown format of config file `{}`, expected a `.toml`, `.yaml` or `.yml` file",
+                path.display()
+            ),
+        }
+    }
+
+    /// Find the path given with `--config` or `CONFIG`. This is done before the commandline is parsed, since the file provides values for it.
+    pub fn path_from_args(args: impl IntoIterator<Item = std::ffi::OsString>) -> Option<PathBuf> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            } else if arg == "--config" {
+                return args.next().map(PathBuf::from);
+            } else if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--config=")) {
+                return Some(PathBuf::from(path));
+            }
+        }
+        std::env::var_os("CONFIG").map(PathBuf::from)
+    }
+
+    /// Put the options in the file in front of the commandline `args`, to parse them together.
+    ///
+    /// Options given in `args` or set as environment variables are left out of the file, together with the options
+    /// they conflict with, e.g `discord-webhook` when `--discord-webhook-file` is given.
+    /// This gives the precedence commandline > environment > file > defaults.
+    pub fn merge_args(
+        &self,
+        args: impl IntoIterator<Item = OsString>,
+    ) -> Result<Vec<OsString>, eyre::Report> {
+        let mut command = <crate::Opts as clap::CommandFactory>::command();
+        // fills in the arguments of groups
+        command.build();
+        let mut args = args.into_iter();
+        let mut merged = vec![args
+            .next()
+            .unwrap_or_else(|| command.get_name().to_owned().into())];
+        let args = args.collect::<Vec<_>>();
+        let given = command
+            .get_arguments()
+            .filter(|arg| is_given(arg, &args))
+            .collect::<Vec<_>>();
+        for (key, value) in &self.options {
+            let id = key.replace('-', "_");
+            let Some(arg) = command
+                .get_arguments()
+                .find(|a| a.get_id() == id.as_str() && a.get_long().is_some())
+                .filter(|_| !matches!(id.as_str(), "config" | "help" | "version"))
+            else {
+                eyre::bail!("unknown option `{key}` in config file");
+            };
+            if given
+                .iter()
+                .any(|other| other.get_id() == arg.get_id() || excludes(&command, arg, other))
+            {
+                continue;
+            }
+            let value = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => eyre::bail!(
+                    "option `{key}` in config file should be a string, number or boolean"
+                ),
+            };
+            if arg.get_action().takes_values() {
+                merged.push(format!("--{key}={value}").into());
+            } else if value == "true" {
+                // flags can't be given a value
+                merged.push(format!("--{key}").into());
+            }
+        }
+        merged.extend(args);
+        Ok(merged)
+    }
+
+    /// Check the parts of the file that depend on commandline options.
+    ///
+    /// A `jsonl` sink can only write to stdout when logs are written to `log_file`, otherwise the two would be mixed.
+    pub fn validate(&self, log_file: Option<&Path>) -> Result<(), eyre::Report> {
+        for (i, sink) in self.sinks.iter().enumerate() {
+            if let crate::sink::SinkConfig::Jsonl { path } = sink {
+                if path.as_os_str() == "-" && log_file.is_none() {
+                    eyre::bail!(
+                        "sink in `sinks[{i}]` writes to stdout, which needs logs to be written to `--log-file`"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the config file of `opts` again and parse their commandline with it.
+    ///
+    /// The environment is read again, but the commandline isn't, only [`crate::Opts::args`].
+    pub fn reload(opts: &crate::Opts) -> Result<crate::Opts, eyre::Report> {
+        let Some(ref path) = opts.config else {
+            eyre::bail!("no config file given");
+        };
+        let config = Self::load(path)?;
+        let args = config.merge_args(opts.args.iter().cloned())?;
+        let mut reloaded = <crate::Opts as clap::Parser>::try_parse_from(args)
+            .wrap_err("options are not valid with the new config file")?;
+        config.validate(reloaded.log_file.as_deref())?;
+        reloaded.args = opts.args.clone();
+        reloaded.config_file = config;
+        reloaded.read_secret_files()?;
+        Ok(reloaded)
+    }
+}
+
+/// Validate the configuration without starting the bot.
+///
+/// Gets a token, resolves the channels and tests the webhooks.
+pub async fn check(
+    client: &reqwest::Client,
+    opts: &crate::Opts,
+    health: std::sync::Arc<crate::health::Health>,
+) -> Result<(), eyre::Report> {
+    if let Some(ref path) = opts.config {
+        println!("config file `{}` is valid", path.display());
+    }
+    let subscriber = crate::subscriber::Subscriber::new(client, opts, health)
+        .await
+        .context("could not construct subscriber")?;
+    println!("token belongs to user id {}", subscriber.token_id);
+    for channel in &subscriber.channels {
+        println!("monitoring channel {} ({})", channel.login, channel.id);
+    }
+
+    let http = serenity::http::HttpBuilder::without_token()
+        .client(client.clone())
+        .build();
+    let mut webhooks = vec![("--discord-webhook".to_owned(), opts.discord_webhook())];
+    if let Some(ref ops) = opts.ops_webhook {
+        webhooks.push(("--ops-webhook".to_owned(), ops));
+    }
+    for (i, route) in opts.config_file.routes.iter().enumerate() {
+        for destination in &route.to {
+            webhooks.push((format!("routes[{i}]"), &destination.webhook));
+        }
+    }
+    for (i, filter) in opts.config_file.filters.iter().enumerate() {
+        for destination in &filter.to {
+            webhooks.push((format!("filters[{i}]"), &destination.webhook));
+        }
+    }
+    for (name, url) in webhooks {
+        let webhook = serenity::model::webhook::Webhook::from_url(&http, url.secret())
+            .await
+            .wrap_err_with(|| format!("webhook in `{name}` is not working"))?;
+        println!(
+            "webhook in `{name}` is working, posting as {}",
+            webhook.name.as_deref().unwrap_or("<unnamed>")
+        );
+    }
+    for (i, config) in opts.config_file.sinks.iter().enumerate() {
+        let sink = crate::sink::Configured::new(client, config)
+            .await
+            .wrap_err_with(|| format!("sink in `sinks[{i}]` is not working"))?;
+        println!(
+            "sink in `sinks[{i}]` is a {} sink",
+            crate::sink::Sink::name(&sink)
+        );
+    }
+    println!("configuration is valid");
+    Ok(())
+}
+
+/// Whether `arg` is in `args` or set as an environment variable.
+fn is_given(arg: &clap::Arg, args: &[OsString]) -> bool {
+    if arg
+        .get_env()
+        .is_some_and(|env| std::env::var_os(env).is_some())
+    {
+        return true;
+    }
+    let (long, short) = (arg.get_long(), arg.get_short());
+    args.iter()
+        .filter_map(|a| a.to_str())
+        .take_while(|a| *a != "--")
+        .any(|a| match a.strip_prefix("--") {
+            Some(a) => long.is_some_and(|long| a.split('=').next() == Some(long)),
+            None => {
+                short.is_some_and(|short| a.strip_prefix('-').is_some_and(|a| a.starts_with(short)))
+            }
+        })
+}
+
+/// Whether two options can't be given together, because one conflicts with the other or they are in the same exclusive group.
+fn excludes(command: &clap::Command, a: &clap::Arg, b: &clap::Arg) -> bool {
+    let conflicts = |a: &clap::Arg, b: &clap::Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|c| c.get_id() == b.get_id())
+    };
+    conflicts(a, b)
+        || conflicts(b, a)
+        || command.get_groups().any(|group| {
+            !group.clone().is_multiple()
+                && group.get_args().any(|id| id == a.get_id())
+                && group.get_args().any(|id| id == b.get_id())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commandline_overrides_file() {
+        let config = toml::from_str::<Config>(
+            r#"
+            discord-webhook = "https://discord.com/api/webhooks/111111111111/aaaaaaaaaaaaaaa"
+            access-token = "abcdefghijklmnopqrstuvwxyz0123"
+            channel-login = "twitch"
+            no-target-info = true
+            chat-context-file = false
+            "#,
+        )
+        .unwrap();
+        let args = config
+            .merge_args(
+                ["twitch-discord-moderation", "--channel-login", "justintv"].map(OsString::from),
+            )
+            .unwrap();
+        let opts = <crate::Opts as clap::Parser>::try_parse_from(args).unwrap();
+        assert_eq!(opts.channel_login.as_deref(), Some("justintv"));
+        assert!(opts.discord_webhook.is_some());
+        assert!(opts.no_target_info);
+        assert!(!opts.chat_context_file);
+
+        let config = toml::from_str::<Config>("no-such-option = 1").unwrap();
+        assert!(config.merge_args([]).is_err());
+    }
+
+    #[test]
+    fn secret_files_override_file() {
+        let config = toml::from_str::<Config>(
+            r#"
+            discord-webhook = "https://discord.com/api/webhooks/111111111111/aaaaaaaaaaaaaaa"
+            access-token = "abcdefghijklmnopqrstuvwxyz0123"
+            "#,
+        )
+        .unwrap();
+        let args = config
+            .merge_args(
+                [
+                    "twitch-discord-moderation",
+                    "--discord-webhook-file",
+                    "/run/secrets/webhook",
+                    "--access-token-file=/run/secrets/token",
+                ]
+                .map(OsString::from),
+            )
+            .unwrap();
+        let opts = <crate::Opts as clap::Parser>::try_parse_from(args).unwrap();
+        assert!(opts.discord_webhook.is_none() && opts.access_token.is_none());
+        assert_eq!(
+            opts.discord_webhook_file.as_deref(),
+            Some(Path::new("/run/secrets/webhook"))
+        );
+        assert_eq!(
+            opts.access_token_file.as_deref(),
+            Some(Path::new("/run/secrets/token"))
+        );
+    }
+
+    #[test]
+    fn stdout_sink_needs_log_file() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[sinks]]
+            type = "jsonl"
+            path = "-"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate(None).is_err());
+        assert!(config.validate(Some(Path::new("bot.log"))).is_ok());
+    }
+}
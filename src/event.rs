@@ -0,0 +1,808 @@
+//! The event format that sinks, stores and exports consume.
+//!
+//! Unlike the payloads from `twitch_api`, this format only changes together with [`VERSION`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use twitch_api::eventsub::{self, channel::moderate};
+use twitch_api::types;
+
+use crate::routing::Subject;
+
+/// Version of [`ModerationEvent`]. Bumped when a field is removed or changes meaning, new fields can be added without bumping it.
+pub const VERSION: u32 = 1;
+
+/// Kinds of actions against what a chatter said, that get [`ModerationEvent::chat_context`] and
+/// [`ModerationEvent::target_info`]
+pub const CHATTER_ACTIONS: &[&str] = &["ban", "timeout", "warn", "delete"];
+
+/// A twitch user.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct User {
+    pub id: String,
+    pub login: String,
+    /// Display name
+    pub name: String,
+}
+
+impl User {
+    pub(crate) fn new(
+        id: &types::UserIdRef,
+        login: &types::UserNameRef,
+        name: &types::DisplayNameRef,
+    ) -> Self {
+        Self {
+            id: id.as_str().to_owned(),
+            login: login.as_str().to_owned(),
+            name: name.as_str().to_owned(),
+        }
+    }
+}
+
+/// A user with `login` as display name, for tests.
+#[cfg(test)]
+pub(crate) fn user(id: &str, login: &str) -> User {
+    User {
+        id: id.to_owned(),
+        login: login.to_owned(),
+        name: login.to_owned(),
+    }
+}
+
+/// More about a user, from Helix `get_users`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct UserInfo {
+    /// When the account was created, in RFC 3339
+    pub created_at: String,
+    pub profile_image_url: Option<String>,
+    /// `partner`, `affiliate` or empty
+    pub broadcaster_type: String,
+}
+
+/// A chat message.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessage {
+    /// When twitch sent the message, in RFC 3339
+    pub timestamp: String,
+    pub message_id: String,
+    pub text: String,
+}
+
+/// A ban, from Helix `get_banned_users`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Ban {
+    /// When the user was banned, in RFC 3339
+    pub created_at: String,
+    /// When a timeout ends, in RFC 3339, `None` for a ban
+    pub expires_at: Option<String>,
+    pub moderator: User,
+    pub reason: String,
+}
+
+/// Chat modes of a channel.
+///
+/// The non-moderator chat delay is left out. Helix has it, but the `channel.chat_settings.update` events don't, so
+/// after the first update it would only be the value from when the bot started, and changes to it can't be posted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChatSettings {
+    pub emote_mode: bool,
+    /// How long users have to follow to chat, in minutes, `None` if followers-only mode is off
+    pub follower_mode_minutes: Option<u64>,
+    /// How long users have to wait between messages, in seconds, `None` if slow mode is off
+    pub slow_mode_seconds: Option<u64>,
+    pub subscriber_mode: bool,
+    pub unique_chat_mode: bool,
+}
+
+/// A moderator or VIP of a channel.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Member {
+    pub user: User,
+    /// When the user was added, in RFC 3339, `None` if they were added before the bot started
+    pub added_at: Option<String>,
+}
+
+/// What moderators did during a stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StreamSummary {
+    /// When the stream went live, in RFC 3339
+    pub started_at: String,
+    /// Amount of actions by kind
+    pub actions: BTreeMap<String, u64>,
+    /// Amount of actions by login of the moderator
+    pub moderators: BTreeMap<String, u64>,
+    /// Amount of actions by login of the user they were done on
+    pub targets: BTreeMap<String, u64>,
+    /// Amount of terms automod added to the blocked or permitted terms
+    pub automod_terms_added: u64,
+    /// Amount of terms automod removed from the blocked or permitted terms
+    pub automod_terms_removed: u64,
+}
+
+/// What moderators did while Shield Mode was on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ShieldSummary {
+    /// When Shield Mode was turned on, in RFC 3339
+    pub started_at: String,
+    pub bans: u64,
+    pub timeouts: u64,
+    pub deletes: u64,
+    /// Amount of bans, timeouts and deletes by login of the moderator
+    pub moderators: BTreeMap<String, u64>,
+    /// Logins of banned users
+    pub banned: BTreeSet<String>,
+    /// Login of the first user actioned
+    pub first_target: Option<String>,
+    /// Login of the last user actioned
+    pub last_target: Option<String>,
+}
+
+/// Something a moderator did.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ModerationEvent {
+    /// Version of this format, see [`VERSION`]
+    pub version: u32,
+    /// Kind of action, e.g `ban` or `emoteonlyoff`
+    pub kind: String,
+    /// When twitch sent the event, in RFC 3339
+    pub timestamp: String,
+    /// Channel the action was done in
+    pub channel: User,
+    /// Channel the action originated from, for actions shared from another channel in shared chat
+    #[serde(default)]
+    pub source_channel: Option<User>,
+    /// Who did the action, `None` when twitch doesn't say, e.g for `chat_settings_update` or `moderator_add`, or no moderator was involved, e.g for `unban_request`
+    #[serde(default)]
+    pub moderator: Option<User>,
+    /// User the action was done on
+    #[serde(default)]
+    pub target: Option<User>,
+    /// More about `target`, for bans, timeouts, warnings and deletes unless `--no-target-info` is given
+    #[serde(default)]
+    pub target_info: Option<UserInfo>,
+    /// Reason of a ban, timeout or warning, or the message of the moderator resolving an unban request
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Length of a timeout, the slow mode delay or how long users have to follow for followers-only mode
+    #[serde(default)]
+    pub duration_seconds: Option<u64>,
+    /// When a timeout ends, in RFC 3339
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    /// Body of a deleted message, the text of an unban request, or a message of a suspicious user or of a user ignoring a warning
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Id of a deleted message, or a message of a suspicious user or of a user ignoring a warning
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// Terms added to or removed from automod
+    #[serde(default)]
+    pub terms: Vec<String>,
+    /// The terms were added or removed by automod and are temporary
+    #[serde(default)]
+    pub temporary: bool,
+    /// Chat rules cited in a warning
+    #[serde(default)]
+    pub rules: Vec<String>,
+    /// Recent messages of `target` in the channel, oldest first, for bans, timeouts, warnings and deletes if `--chat-context` is set
+    #[serde(default)]
+    pub chat_context: Vec<ChatMessage>,
+    /// Id of an unban request, to match `unban_request_resolve` to its `unban_request`
+    #[serde(default)]
+    pub unban_request_id: Option<String>,
+    /// How an unban request was resolved, `approved`, `denied` or `canceled`, or how a suspicious user is treated, `active_monitoring`, `restricted` or `none`
+    #[serde(default)]
+    pub status: Option<String>,
+    /// The ban of `target` an unban request is about
+    #[serde(default)]
+    pub ban: Option<Ban>,
+    /// How likely a suspicious user is evading a ban, `unknown`, `possible` or `likely`
+    #[serde(default)]
+    pub ban_evasion: Option<String>,
+    /// Why a user is suspicious, e.g `manually_added` or `shared_channel_ban`
+    #[serde(default)]
+    pub suspicious_types: Vec<String>,
+    /// Channels a suspicious user is banned in that share bans with this channel
+    #[serde(default)]
+    pub shared_ban_channels: Vec<User>,
+    /// Chat settings after a `chat_settings_update`
+    #[serde(default)]
+    pub chat_settings: Option<ChatSettings>,
+    /// Chat settings before a `chat_settings_update`, if they were known
+    #[serde(default)]
+    pub previous_chat_settings: Option<ChatSettings>,
+    /// Moderators of the channel for `roster`
+    #[serde(default)]
+    pub moderators: Vec<Member>,
+    /// VIPs of the channel for `roster`
+    #[serde(default)]
+    pub vips: Vec<Member>,
+    /// ID of the stream the event happened in, `offline` if the channel wasn't live, or empty if that isn't known
+    #[serde(default)]
+    pub stream: String,
+    /// What moderators did during the stream for `stream_offline`
+    #[serde(default)]
+    pub stream_summary: Option<StreamSummary>,
+    /// What moderators did while Shield Mode was on for `shield_mode_end`
+    #[serde(default)]
+    pub shield_summary: Option<ShieldSummary>,
+    /// The `ban` or `unban` happened while the bot was down, and was found by comparing the banned users when it started, see `--bans-file`
+    #[serde(default)]
+    pub detected_on_reconnect: bool,
+    /// Viewers in a raid
+    #[serde(default)]
+    pub viewer_count: Option<u64>,
+    /// The payload from twitch as is. Its format can change without notice.
+    #[serde(default)]
+    pub raw: serde_json::Value,
+}
+
+impl ModerationEvent {
+    /// An event with only the required fields set.
+    pub fn new(
+        kind: impl Into<String>,
+        timestamp: &types::TimestampRef,
+        channel: User,
+        moderator: Option<User>,
+    ) -> Self {
+        Self {
+            version: VERSION,
+            kind: kind.into(),
+            timestamp: timestamp.as_str().to_owned(),
+            channel,
+            source_channel: None,
+            moderator,
+            target: None,
+            target_info: None,
+            reason: None,
+            duration_seconds: None,
+            expires_at: None,
+            message: None,
+            message_id: None,
+            terms: vec![],
+            temporary: false,
+            rules: vec![],
+            chat_context: vec![],
+            unban_request_id: None,
+            status: None,
+            ban: None,
+            ban_evasion: None,
+            suspicious_types: vec![],
+            shared_ban_channels: vec![],
+            chat_settings: None,
+            previous_chat_settings: None,
+            moderators: vec![],
+            vips: vec![],
+            stream: String::new(),
+            stream_summary: None,
+            shield_summary: None,
+            detected_on_reconnect: false,
+            viewer_count: None,
+            raw: serde_json::Value::Null,
+        }
+    }
+
+    /// Convert a `channel.moderate` notification.
+    pub fn from_moderate(
+        p: &<eventsub::channel::ChannelModerateV2 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        use moderate::ActionV2;
+
+        let action = &p.action;
+        let source_channel = match (
+            &p.source_broadcaster_user_id,
+            &p.source_broadcaster_user_login,
+            &p.source_broadcaster_user_name,
+        ) {
+            (Some(id), Some(login), Some(name)) => Some(User::new(id, login, name)),
+            _ => None,
+        };
+        let (expires_at, duration_seconds) = match action {
+            ActionV2::Timeout(moderate::Timeout { expires_at, .. })
+            | ActionV2::SharedChatTimeout(moderate::SharedChatTimeout(moderate::Timeout {
+                expires_at,
+                ..
+            })) => (
+                Some(expires_at.as_str().to_owned()),
+                Some(
+                    (expires_at.to_utc() - timestamp.to_utc())
+                        .whole_seconds()
+                        .max(0) as u64,
+                ),
+            ),
+            ActionV2::Slow(moderate::Slow {
+                wait_time_seconds, ..
+            }) => (None, Some(*wait_time_seconds as u64)),
+            ActionV2::Followers(moderate::Followers {
+                follow_duration_minutes,
+                ..
+            }) => (None, Some(*follow_duration_minutes as u64 * 60)),
+            _ => (None, None),
+        };
+        let message_id = match action {
+            ActionV2::Delete(moderate::Delete { message_id, .. })
+            | ActionV2::SharedChatDelete(moderate::SharedChatDelete(moderate::Delete {
+                message_id,
+                ..
+            })) => Some(message_id.as_str().to_owned()),
+            _ => None,
+        };
+        let reason = match action {
+            ActionV2::ApproveUnbanRequest(moderate::UnbanRequest {
+                moderator_message, ..
+            })
+            | ActionV2::DenyUnbanRequest(moderate::UnbanRequest {
+                moderator_message, ..
+            }) => Some(moderator_message.clone()),
+            _ => crate::subscriber::action_reason(action).map(str::to_owned),
+        };
+        let (terms, temporary) = match action {
+            ActionV2::AddBlockedTerm(terms)
+            | ActionV2::AddPermittedTerm(terms)
+            | ActionV2::RemoveBlockedTerm(terms)
+            | ActionV2::RemovePermittedTerm(terms) => (terms.terms.clone(), terms.from_automod),
+            _ => (vec![], false),
+        };
+        let rules = match action {
+            ActionV2::Warn(moderate::Warn {
+                chat_rules_cited: Some(rules),
+                ..
+            }) => rules.clone(),
+            _ => vec![],
+        };
+        let viewer_count = match action {
+            ActionV2::Raid(moderate::Raid { viewer_count, .. }) => Some(*viewer_count as u64),
+            _ => None,
+        };
+        Self {
+            source_channel,
+            target: crate::subscriber::action_target(action)
+                .map(|(id, login, name)| User::new(id, login, name)),
+            reason,
+            duration_seconds,
+            expires_at,
+            message: crate::subscriber::action_message(action).map(str::to_owned),
+            message_id,
+            terms,
+            temporary,
+            rules,
+            viewer_count,
+            raw: raw(p),
+            ..Self::new(
+                crate::subscriber::action_kind(action),
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                Some(User::new(
+                    &p.moderator_user_id,
+                    &p.moderator_user_login,
+                    &p.moderator_user_name,
+                )),
+            )
+        }
+    }
+
+    /// Convert a `channel.unban_request.create` notification.
+    pub fn from_unban_request_create(
+        p: &<eventsub::channel::ChannelUnbanRequestCreateV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let user = User::new(&p.user_id, &p.user_login, &p.user_name);
+        Self {
+            target: Some(user),
+            message: Some(p.text.clone()),
+            unban_request_id: Some(p.id.as_str().to_owned()),
+            raw: raw(p),
+            ..Self::new(
+                "unban_request",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                None,
+            )
+        }
+    }
+
+    /// Convert a `channel.unban_request.resolve` notification.
+    pub fn from_unban_request_resolve(
+        p: &<eventsub::channel::ChannelUnbanRequestResolveV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let user = User::new(&p.user_id, &p.user_login, &p.user_name);
+        let moderator = match (
+            &p.moderator_user_id,
+            &p.moderator_user_login,
+            &p.moderator_user_name,
+        ) {
+            (Some(id), Some(login), Some(name)) => Some(User::new(id, login, name)),
+            // the user canceled the request
+            _ => None,
+        };
+        Self {
+            target: Some(user),
+            reason: p.resolution_text.clone().filter(|t| !t.is_empty()),
+            unban_request_id: Some(p.id.as_str().to_owned()),
+            status: name(&p.status),
+            raw: raw(p),
+            ..Self::new(
+                "unban_request_resolve",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                moderator,
+            )
+        }
+    }
+
+    /// Convert a `channel.suspicious_user.update` notification.
+    pub fn from_suspicious_user_update(
+        p: &<eventsub::channel::ChannelSuspiciousUserUpdateV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self {
+            target: Some(User::new(&p.user_id, &p.user_login, &p.user_name)),
+            status: name(&p.low_trust_status),
+            raw: raw(p),
+            ..Self::new(
+                "suspicious_user_update",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                Some(User::new(
+                    &p.moderator_user_id,
+                    &p.moderator_user_login,
+                    &p.moderator_user_name,
+                )),
+            )
+        }
+    }
+
+    /// Convert a `channel.suspicious_user.message` notification.
+    ///
+    /// [`ModerationEvent::shared_ban_channels`] only have their id set, the subscriber looks up the rest.
+    pub fn from_suspicious_user_message(
+        p: &<eventsub::channel::ChannelSuspiciousUserMessageV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let user = User::new(&p.user_id, &p.user_login, &p.user_name);
+        Self {
+            target: Some(user),
+            message: Some(p.message.text.clone()),
+            message_id: Some(p.message.message_id.as_str().to_owned()),
+            status: name(&p.low_trust_status),
+            ban_evasion: name(&p.ban_evasion_evaluation),
+            suspicious_types: p.types.iter().filter_map(name).collect(),
+            shared_ban_channels: p
+                .shared_ban_channel_ids
+                .iter()
+                .map(|id| User {
+                    id: id.as_str().to_owned(),
+                    login: String::new(),
+                    name: String::new(),
+                })
+                .collect(),
+            raw: raw(p),
+            ..Self::new(
+                "suspicious_user_message",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                None,
+            )
+        }
+    }
+
+    /// Convert a `channel.shield_mode.begin` notification.
+    pub fn from_shield_mode_begin(
+        p: &<eventsub::channel::ChannelShieldModeBeginV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self {
+            raw: raw(p),
+            ..Self::new(
+                "shield_mode_begin",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                Some(User::new(
+                    &p.moderator_user_id,
+                    &p.moderator_user_login,
+                    &p.moderator_user_name,
+                )),
+            )
+        }
+    }
+
+    /// Convert a `channel.shield_mode.end` notification.
+    pub fn from_shield_mode_end(
+        p: &<eventsub::channel::ChannelShieldModeEndV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self {
+            raw: raw(p),
+            ..Self::new(
+                "shield_mode_end",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                Some(User::new(
+                    &p.moderator_user_id,
+                    &p.moderator_user_login,
+                    &p.moderator_user_name,
+                )),
+            )
+        }
+    }
+
+    /// Convert a `channel.warning.acknowledge` notification.
+    pub fn from_warning_acknowledge(
+        p: &<eventsub::channel::ChannelWarningAcknowledgeV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let user = User::new(&p.user_id, &p.user_login, &p.user_name);
+        Self {
+            target: Some(user),
+            raw: raw(p),
+            ..Self::new(
+                "warning_acknowledge",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                None,
+            )
+        }
+    }
+
+    /// A `warning_ignored` event for a chat message of a user that hasn't acknowledged their warning.
+    pub fn from_ignored_warning(
+        p: &<eventsub::channel::ChannelChatMessageV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let user = User::new(
+            &p.chatter_user_id,
+            &p.chatter_user_login,
+            &p.chatter_user_name,
+        );
+        Self {
+            target: Some(user),
+            message: Some(p.message.text.clone()),
+            message_id: Some(p.message_id.as_str().to_owned()),
+            raw: raw(p),
+            ..Self::new(
+                "warning_ignored",
+                timestamp,
+                User::new(
+                    &p.broadcaster_user_id,
+                    &p.broadcaster_user_login,
+                    &p.broadcaster_user_name,
+                ),
+                None,
+            )
+        }
+    }
+
+    /// Convert a `channel.chat_settings.update` notification.
+    pub fn from_chat_settings_update(
+        p: &<eventsub::channel::ChannelChatSettingsUpdateV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let channel = User::new(
+            &p.broadcaster_user_id,
+            &p.broadcaster_user_login,
+            &p.broadcaster_user_name,
+        );
+        Self {
+            chat_settings: Some(ChatSettings {
+                emote_mode: p.emote_mode,
+                follower_mode_minutes: p
+                    .follower_mode
+                    .then(|| p.follower_mode_duration_minutes.unwrap_or(0) as u64),
+                slow_mode_seconds: p
+                    .slow_mode
+                    .then(|| p.slow_mode_wait_time_seconds.unwrap_or(0) as u64),
+                subscriber_mode: p.subscriber_mode,
+                unique_chat_mode: p.unique_chat_mode,
+            }),
+            raw: raw(p),
+            ..Self::new("chat_settings_update", timestamp, channel, None)
+        }
+    }
+
+    /// Convert a `channel.moderator.add` notification.
+    pub fn from_moderator_add(
+        p: &<eventsub::channel::ChannelModeratorAddV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self::roster_change(
+            "moderator_add",
+            timestamp,
+            User::new(
+                &p.broadcaster_user_id,
+                &p.broadcaster_user_login,
+                &p.broadcaster_user_name,
+            ),
+            User::new(&p.user_id, &p.user_login, &p.user_name),
+            raw(p),
+        )
+    }
+
+    /// Convert a `channel.moderator.remove` notification.
+    pub fn from_moderator_remove(
+        p: &<eventsub::channel::ChannelModeratorRemoveV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self::roster_change(
+            "moderator_remove",
+            timestamp,
+            User::new(
+                &p.broadcaster_user_id,
+                &p.broadcaster_user_login,
+                &p.broadcaster_user_name,
+            ),
+            User::new(&p.user_id, &p.user_login, &p.user_name),
+            raw(p),
+        )
+    }
+
+    /// Convert a `channel.vip.add` notification.
+    pub fn from_vip_add(
+        p: &<eventsub::channel::ChannelVipAddV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self::roster_change(
+            "vip_add",
+            timestamp,
+            User::new(
+                &p.broadcaster_user_id,
+                &p.broadcaster_user_login,
+                &p.broadcaster_user_name,
+            ),
+            User::new(&p.user_id, &p.user_login, &p.user_name),
+            raw(p),
+        )
+    }
+
+    /// Convert a `channel.vip.remove` notification.
+    pub fn from_vip_remove(
+        p: &<eventsub::channel::ChannelVipRemoveV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        Self::roster_change(
+            "vip_remove",
+            timestamp,
+            User::new(
+                &p.broadcaster_user_id,
+                &p.broadcaster_user_login,
+                &p.broadcaster_user_name,
+            ),
+            User::new(&p.user_id, &p.user_login, &p.user_name),
+            raw(p),
+        )
+    }
+
+    /// Convert a `stream.online` notification.
+    pub fn from_stream_online(
+        p: &<eventsub::stream::StreamOnlineV1 as eventsub::EventSubscription>::Payload,
+    ) -> Self {
+        let channel = User::new(
+            &p.broadcaster_user_id,
+            &p.broadcaster_user_login,
+            &p.broadcaster_user_name,
+        );
+        Self {
+            stream: p.id.as_str().to_owned(),
+            raw: raw(p),
+            ..Self::new("stream_online", &p.started_at, channel, None)
+        }
+    }
+
+    /// Convert a `stream.offline` notification.
+    pub fn from_stream_offline(
+        p: &<eventsub::stream::StreamOfflineV1 as eventsub::EventSubscription>::Payload,
+        timestamp: &types::TimestampRef,
+    ) -> Self {
+        let channel = User::new(
+            &p.broadcaster_user_id,
+            &p.broadcaster_user_login,
+            &p.broadcaster_user_name,
+        );
+        Self {
+            raw: raw(p),
+            ..Self::new("stream_offline", timestamp, channel, None)
+        }
+    }
+
+    /// A moderator or VIP added or removed, twitch doesn't say by whom.
+    fn roster_change(
+        kind: &str,
+        timestamp: &types::TimestampRef,
+        channel: User,
+        user: User,
+        raw: serde_json::Value,
+    ) -> Self {
+        Self {
+            target: Some(user),
+            raw,
+            ..Self::new(kind, timestamp, channel, None)
+        }
+    }
+
+    /// What the event is about, for matching against routes and filters.
+    pub fn subject(&self) -> Subject<'_> {
+        Subject {
+            kind: &self.kind,
+            channel: &self.channel.login,
+            moderator: self.moderator.as_ref().map(|m| m.login.as_str()),
+            target: self.target.as_ref().map(|t| t.login.as_str()),
+            reason: self.reason.as_deref(),
+            message: self.message.as_deref(),
+        }
+    }
+}
+
+/// Name of an enum from twitch, e.g `approved`, as twitch names it.
+fn name(value: &impl serde::Serialize) -> Option<String> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// The payload from twitch as json, for [`ModerationEvent::raw`].
+fn raw(payload: &impl serde::Serialize) -> serde_json::Value {
+    serde_json::to_value(payload).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "could not serialize payload");
+        serde_json::Value::Null
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_have_defaults() {
+        let event: ModerationEvent = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "kind": "emoteonly",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "channel": user("1", "twitch"),
+            "moderator": user("1", "justintv"),
+        }))
+        .unwrap();
+        assert_eq!(event.subject().kind, "emoteonly");
+        assert!(event.target.is_none() && event.terms.is_empty() && event.raw.is_null());
+
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["version"], VERSION);
+        assert_eq!(
+            serde_json::from_value::<ModerationEvent>(value).unwrap(),
+            event
+        );
+    }
+}
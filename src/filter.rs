@@ -0,0 +1,232 @@
+//! Filtering noisy events, see `filters` in the config file.
+
+use std::time::Duration;
+
+use crate::routing::{Destination, Subject};
+use crate::webhook::MAX_MESSAGE_LEN;
+
+fn deserialize_regex<'de, D: serde::Deserializer<'de>>(
+    d: D,
+) -> Result<Option<regex::Regex>, D::Error> {
+    let Some(regex) = <Option<String> as serde::Deserialize>::deserialize(d)? else {
+        return Ok(None);
+    };
+    regex::Regex::new(&regex)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let duration = <String as serde::Deserialize>::deserialize(d)?;
+    humantime::parse_duration(&duration).map_err(serde::de::Error::custom)
+}
+
+fn default_digest_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+/// Conditions on an event. All given conditions have to match, empty conditions match everything.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Conditions {
+    /// Kinds of actions, e.g `delete` or `timeout`
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// Logins of channels the action happened in
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Logins of moderators that did the action
+    #[serde(default)]
+    pub moderators: Vec<String>,
+    /// Match actions done by `--channel-bot-name`
+    #[serde(default)]
+    pub channel_bot: bool,
+    /// Logins of users the action was done on
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Regex on the reason of a ban, timeout or warning, or the message of a resolved unban request
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub reason: Option<regex::Regex>,
+    /// Regex on the body of a deleted message
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub message: Option<regex::Regex>,
+}
+
+/// Regexes are equal when they are written the same.
+impl PartialEq for Conditions {
+    fn eq(&self, other: &Self) -> bool {
+        self.actions == other.actions
+            && self.channels == other.channels
+            && self.moderators == other.moderators
+            && self.channel_bot == other.channel_bot
+            && self.targets == other.targets
+            && self.reason.as_ref().map(regex::Regex::as_str)
+                == other.reason.as_ref().map(regex::Regex::as_str)
+            && self.message.as_ref().map(regex::Regex::as_str)
+                == other.message.as_ref().map(regex::Regex::as_str)
+    }
+}
+
+impl Conditions {
+    pub fn matches(&self, subject: &Subject<'_>, channel_bot_name: Option<&str>) -> bool {
+        subject.matches(
+            &self.actions,
+            &self.channels,
+            &self.moderators,
+            &self.targets,
+        ) && (!self.channel_bot
+            || channel_bot_name
+                .zip(subject.moderator)
+                .is_some_and(|(bot, m)| bot.eq_ignore_ascii_case(m)))
+            && self
+                .reason
+                .as_ref()
+                .is_none_or(|r| subject.reason.is_some_and(|reason| r.is_match(reason)))
+            && self
+                .message
+                .as_ref()
+                .is_none_or(|r| subject.message.is_some_and(|message| r.is_match(message)))
+    }
+}
+
+/// What to do with events matched by a filter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Then {
+    /// Don't post the event.
+    #[default]
+    Drop,
+    /// Post the event to the destinations of the filter instead of the routes.
+    Route,
+    /// Collect the events and post a summary of them to the destinations of the filter every `digest-interval`.
+    Digest,
+}
+
+/// Catches events that match `include` but not `exclude`. The first matching filter applies.
+///
+/// ```toml
+/// [[filters]]
+/// include = { channel-bot = true, actions = ["delete"] }
+/// exclude = { message = "(?i)clip" }
+/// then = "digest"
+/// digest-interval = "1h"
+/// ```
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Filter {
+    #[serde(default)]
+    pub include: Conditions,
+    pub exclude: Option<Conditions>,
+    #[serde(default)]
+    pub then: Then,
+    /// Where to post for `route` and `digest`, defaults to `--discord-webhook`.
+    #[serde(default)]
+    pub to: Vec<Destination>,
+    #[serde(
+        default = "default_digest_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub digest_interval: Duration,
+}
+
+impl Filter {
+    pub fn matches(&self, subject: &Subject<'_>, channel_bot_name: Option<&str>) -> bool {
+        self.include.matches(subject, channel_bot_name)
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|e| e.matches(subject, channel_bot_name))
+    }
+}
+
+/// The first filter matching an event and its index.
+pub fn find<'a>(
+    filters: &'a [Filter],
+    subject: &Subject<'_>,
+    channel_bot_name: Option<&str>,
+) -> Option<(usize, &'a Filter)> {
+    filters
+        .iter()
+        .enumerate()
+        .find(|(_, f)| f.matches(subject, channel_bot_name))
+}
+
+/// Events collected for a digest.
+#[derive(Debug)]
+pub struct Digest {
+    pub since: std::time::Instant,
+    pub lines: Vec<String>,
+}
+
+impl Digest {
+    /// Render the digest as one message, leaving out lines that don't fit.
+    pub fn render(&self) -> String {
+        let mut message = format!(
+            "📋_Twitch Moderation_ | {} filtered actions in the last {}",
+            self.lines.len(),
+            humantime::format_duration(Duration::from_secs(self.since.elapsed().as_secs()))
+        );
+        for (i, line) in self.lines.iter().enumerate() {
+            let more = format!("\n…and {} more", self.lines.len() - i);
+            if message.len() + 1 + line.len() + more.len() > MAX_MESSAGE_LEN {
+                message.push_str(&more);
+                break;
+            }
+            message.push('\n');
+            message.push_str(line);
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject<'a>(moderator: &'a str, message: Option<&'a str>) -> Subject<'a> {
+        Subject {
+            kind: "delete",
+            channel: "twitch",
+            moderator: Some(moderator),
+            target: Some("someone"),
+            reason: None,
+            message,
+        }
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let filters = toml::from_str::<crate::config::Config>(
+            r#"
+            [[filters]]
+            include = { channel-bot = true, actions = ["delete"] }
+            exclude = { message = "(?i)clip" }
+            then = "digest"
+            "#,
+        )
+        .unwrap()
+        .filters;
+        let bot = Some("Nightbot");
+
+        let found = find(&filters, &subject("nightbot", Some("buy followers")), bot);
+        assert_eq!(found.map(|(i, f)| (i, f.then)), Some((0, Then::Digest)));
+        assert_eq!(filters[0].digest_interval, default_digest_interval());
+        // excluded
+        assert!(find(&filters, &subject("nightbot", Some("nice CLIP")), bot).is_none());
+        // not the bot
+        assert!(find(&filters, &subject("justintv", Some("buy followers")), bot).is_none());
+        // no bot configured
+        assert!(find(&filters, &subject("nightbot", Some("buy followers")), None).is_none());
+    }
+
+    #[test]
+    fn digest_fits_in_a_message() {
+        let digest = Digest {
+            since: std::time::Instant::now(),
+            lines: vec!["x".repeat(100); 50],
+        };
+        let rendered = digest.render();
+        assert!(rendered.len() <= MAX_MESSAGE_LEN);
+        assert!(rendered.ends_with("more"));
+    }
+}
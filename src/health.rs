@@ -0,0 +1,103 @@
+//! Shared state describing whether the bot is connected and working.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Health of the bot, updated by the subscriber and the webhook.
+#[derive(Debug, Default)]
+pub struct Health {
+    session_id: Mutex<Option<String>>,
+    last_keepalive: Mutex<Option<Instant>>,
+    keepalive_timeout: Mutex<Option<Duration>>,
+    token_expires_at: Mutex<Option<Instant>>,
+    last_post: Mutex<Option<DiscordPost>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DiscordPost {
+    success: bool,
+    at: Instant,
+}
+
+impl Health {
+    /// Set the session id of the websocket connection, `None` when disconnected.
+    pub fn set_session(&self, session_id: Option<String>) {
+        *self.session_id.lock().unwrap() = session_id;
+    }
+
+    /// Set how long we wait for a message before the connection is considered dead.
+    pub fn set_keepalive_timeout(&self, timeout: Duration) {
+        *self.keepalive_timeout.lock().unwrap() = Some(timeout);
+    }
+
+    /// Record that the websocket sent us something, which resets the keepalive timer.
+    pub fn keepalive(&self) {
+        *self.last_keepalive.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Record when the current token expires.
+    pub fn set_token_expiry(&self, expires_in: Duration) {
+        *self.token_expires_at.lock().unwrap() = Some(Instant::now() + expires_in);
+        crate::metrics::TOKEN_EXPIRES_AT
+            .set((time::OffsetDateTime::now_utc() + expires_in).unix_timestamp());
+    }
+
+    /// Record the outcome of a post to discord.
+    pub fn record_post(&self, success: bool) {
+        *self.last_post.lock().unwrap() = Some(DiscordPost {
+            success,
+            at: Instant::now(),
+        });
+    }
+
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    pub fn since_keepalive(&self) -> Option<Duration> {
+        self.last_keepalive.lock().unwrap().map(|i| i.elapsed())
+    }
+
+    pub fn token_expires_in(&self) -> Option<Duration> {
+        self.token_expires_at
+            .lock()
+            .unwrap()
+            .map(|i| i.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether the last post to discord succeeded, `None` if nothing has been posted yet.
+    pub fn last_post_succeeded(&self) -> Option<bool> {
+        self.last_post.lock().unwrap().map(|p| p.success)
+    }
+
+    /// The bot is ready when it has a session, the connection is alive, the token is valid and discord accepts posts.
+    pub fn is_ready(&self) -> bool {
+        let keepalive_ok = match (
+            self.since_keepalive(),
+            *self.keepalive_timeout.lock().unwrap(),
+        ) {
+            (Some(since), Some(timeout)) => since <= timeout,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        self.session_id().is_some()
+            && keepalive_ok
+            && self.token_expires_in().is_some_and(|d| !d.is_zero())
+            && self.last_post_succeeded() != Some(false)
+    }
+
+    /// Report of the current state, as served on `/healthz` and `/readyz`
+    pub fn report(&self) -> serde_json::Value {
+        let last_post = *self.last_post.lock().unwrap();
+        serde_json::json!({
+            "ready": self.is_ready(),
+            "session_id": self.session_id(),
+            "seconds_since_keepalive": self.since_keepalive().map(|d| d.as_secs_f64()),
+            "token_expires_in_seconds": self.token_expires_in().map(|d| d.as_secs()),
+            "last_discord_post": last_post.map(|p| serde_json::json!({
+                "success": p.success,
+                "seconds_ago": p.at.elapsed().as_secs_f64(),
+            })),
+        })
+    }
+}
@@ -0,0 +1,331 @@
+//! Send twitch moderator actions to discord, or anywhere else.
+//!
+//! The bot is built from a [`Subscriber`] that receives events from the eventsub websocket, see [`WebsocketClient`],
+//! and [sinks](Sink) that deliver them as [`ModerationEvent`]s. [`Pipeline`] puts these together, like the bot does,
+//! and can be given handlers of its own.
+
+pub mod bans;
+pub mod chat;
+#[cfg(test)]
+pub mod ci;
+pub mod config;
+pub mod event;
+pub mod filter;
+pub mod health;
+pub mod metrics;
+pub mod pipeline;
+pub mod reload;
+pub mod render;
+pub mod report;
+pub mod roster;
+pub mod routing;
+pub mod server;
+pub mod settings;
+pub mod shield;
+pub mod shutdown;
+pub mod sink;
+pub mod stream;
+pub mod subscriber;
+pub mod supervisor;
+pub mod users;
+pub mod util;
+pub mod webhook;
+
+pub use event::ModerationEvent;
+pub use pipeline::{Pipeline, PipelineBuilder};
+pub use render::Markup;
+pub use sink::Sink;
+pub use subscriber::{Events, Subscriber, WebsocketClient};
+
+use clap::{builder::ArgPredicate, ArgGroup, Parser};
+use eyre::WrapErr;
+
+#[derive(Parser, Debug)]
+#[clap(about, version, long_version = &**util::LONG_VERSION, args_override_self = true,
+    group = ArgGroup::new("token").multiple(false).required(false),
+    group = ArgGroup::new("service").multiple(true).requires("oauth2_service_url"),
+    group = ArgGroup::new("dcf_oauth").multiple(true).requires("dcf_oauth_client_id"),
+    group = ArgGroup::new("channel").multiple(true).required(false),
+)]
+pub struct Opts {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// Path to a TOML or YAML configuration file.
+    ///
+    /// Keys in the file are the long names of these options, e.g `discord-webhook`. Options given on the commandline or as environment variables take precedence over the file.
+    #[clap(long, env, hide_env = true)]
+    pub config: Option<std::path::PathBuf>,
+    /// Settings from `--config`
+    #[clap(skip)]
+    pub config_file: config::Config,
+    /// The commandline these options were parsed from, without the options from `--config`. Parsed again with the file when it's reloaded.
+    #[clap(skip)]
+    pub args: Vec<std::ffi::OsString>,
+    /// How often to check the config file for changes. Changes are also applied on SIGHUP. `0s` disables checking.
+    ///
+    /// Webhooks, the name of the channel bot and the monitored channels are updated without restarting. Other options only apply after a restart.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "5s")]
+    pub config_watch_interval: std::time::Duration,
+    /// URL to discord webhook.
+    #[clap(long, env, hide_env = true, value_parser = is_url, required_unless_present = "discord_webhook_file")]
+    pub discord_webhook: Option<Secret>,
+    /// File containing the URL to discord webhook, e.g a docker secret.
+    #[clap(long, env, hide_env = true, conflicts_with = "discord_webhook")]
+    pub discord_webhook_file: Option<std::path::PathBuf>,
+    /// OAuth2 Access token
+    #[clap(long, env, hide_env = true, group = "token",
+        value_parser = is_token, required_unless_present_any = ["service", "dcf_oauth", "access_token_file"]
+    )]
+    pub access_token: Option<Secret>,
+    /// File containing the OAuth2 Access token, e.g a docker secret.
+    #[clap(long, env, hide_env = true, group = "token")]
+    pub access_token_file: Option<std::path::PathBuf>,
+    /// Name of channel to monitor. If left out, defaults to owner of access token.
+    #[clap(long, env, hide_env = true, group = "channel")]
+    pub channel_login: Option<String>,
+    /// User ID of channel to monitor. If left out, defaults to owner of access token.
+    #[clap(long, env, hide_env = true, group = "channel")]
+    pub channel_id: Option<String>,
+    /// URL to service that provides OAuth2 token. Called on start and whenever the token needs to be refreshed.
+    ///
+    /// This application does not do any refreshing of tokens.
+    #[clap(long, env, hide_env = true, group = "service",
+        value_parser = url::Url::parse, required_unless_present_any = ["token", "dcf_oauth"]
+    )]
+    pub oauth2_service_url: Option<url::Url>,
+    /// Bearer key for authorizing on the OAuth2 service url.
+    #[clap(long, env, hide_env = true, group = "service")]
+    pub oauth2_service_key: Option<Secret>,
+    /// File containing the bearer key for authorizing on the OAuth2 service url, e.g a docker secret.
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        group = "service",
+        conflicts_with = "oauth2_service_key"
+    )]
+    pub oauth2_service_key_file: Option<std::path::PathBuf>,
+    /// Grab token by pointer. See https://tools.ietf.org/html/rfc6901
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        group = "service",
+        default_value_if("oauth2_service_url", ArgPredicate::IsPresent, Some("/access_token"))
+    )]
+    pub oauth2_service_pointer: Option<String>,
+    /// Grab a new token from the OAuth2 service this many seconds before it actually expires. Default is 30 seconds
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        group = "service",
+        default_value_if("oauth2_service_url", ArgPredicate::IsPresent, Some("30"))
+    )]
+    pub oauth2_service_refresh: Option<u64>,
+    /// Client id to get a token. Stores the token data in the path specified by `--dcf-secret` (client id and optional secret is not stored)
+    #[clap(long, env, hide_env = true, group = "dcf_oauth", required_unless_present_any = ["token", "service"])]
+    pub dcf_oauth_client_id: Option<twitch_api::twitch_oauth2::ClientId>,
+    /// Client secret to get a token. Only needed for confidential applications.
+    #[clap(long, env, hide_env = true, group = "dcf_oauth")]
+    pub dcf_oauth_client_secret: Option<twitch_api::twitch_oauth2::ClientSecret>,
+    /// File containing the client secret to get a token, e.g a docker secret.
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        group = "dcf_oauth",
+        conflicts_with = "dcf_oauth_client_secret"
+    )]
+    pub dcf_oauth_client_secret_file: Option<std::path::PathBuf>,
+    /// Path for storing DCF oauth.
+    #[clap(
+        long,
+        env,
+        hide_env = true,
+        group = "dcf_oauth",
+        default_value = "./.dcf_secret"
+    )]
+    pub dcf_secret_path: Option<std::path::PathBuf>,
+    ///
+    /// Name of channel bot. Filters in the config file can match its actions with `channel-bot = true`.
+    #[clap(long, env, hide_env = true)]
+    pub channel_bot_name: Option<String>,
+    /// Don't look up the account age, profile image and broadcaster type of users that are banned, timed out, warned or have a message deleted.
+    #[clap(long, env, hide_env = true)]
+    pub no_target_info: bool,
+    /// How long to remember looked up users before asking twitch again.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "10m")]
+    pub target_info_ttl: std::time::Duration,
+    /// Keep the last N chat messages of every chatter and show them in posts about bans, timeouts, warnings and deletes. `0` keeps no messages.
+    ///
+    /// Needs the `user:read:chat` scope. Chat is also read with `0` when the token has the scope, to post `warning_ignored`.
+    #[clap(long, env, hide_env = true, default_value = "0")]
+    pub chat_context: usize,
+    /// Always attach the chat context to discord posts as a text file, instead of only when it doesn't fit in the post.
+    #[clap(long, env, hide_env = true)]
+    pub chat_context_file: bool,
+    /// Edit timeout posts when the timeout ends, to show that it expired.
+    #[clap(long, env, hide_env = true)]
+    pub mark_expired_timeouts: bool,
+    /// Post the moderators and VIPs of every channel this often, e.g `1d`.
+    ///
+    /// Needs the `moderator:read:moderators` and `moderator:read:vips` scopes.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration)]
+    pub roster_interval: Option<std::time::Duration>,
+    /// Remember the banned users of every channel in this file, to post the bans and unbans that happened while the bot was down when it starts.
+    ///
+    /// Needs the `moderator:read:banned_users` scope.
+    #[clap(long, env, hide_env = true)]
+    pub bans_file: Option<std::path::PathBuf>,
+    /// Report errors to the discord webhook and keep retrying instead of making the program exit.
+    ///
+    /// Reports go to `--ops-webhook` if it is set.
+    #[clap(long, env, hide_env = true)]
+    pub discord_error_report: bool,
+    /// URL to a discord webhook for reporting warnings and errors, and when the bot has recovered from them. Implies `--discord-error-report`.
+    #[clap(long, env, hide_env = true, value_parser = is_url)]
+    pub ops_webhook: Option<Secret>,
+    /// File containing the URL to the discord webhook for reporting, e.g a docker secret.
+    #[clap(long, env, hide_env = true, conflicts_with = "ops_webhook")]
+    pub ops_webhook_file: Option<std::path::PathBuf>,
+    /// ID of a discord role to mention in error reports.
+    #[clap(long, env, hide_env = true)]
+    pub ops_mention_role: Option<std::num::NonZeroU64>,
+    /// Minimum time between two reports.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub ops_min_interval: std::time::Duration,
+    /// Minimum time before reporting the same error again.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "1h")]
+    pub ops_repeat_interval: std::time::Duration,
+    /// Give up after this many errors in a row. 0 means never give up.
+    ///
+    /// Errors that can not be fixed by restarting, like a bad webhook url or a revoked token, always stop the bot.
+    #[clap(long, env, hide_env = true, default_value = "10")]
+    pub retry_max_attempts: u32,
+    /// Time to wait before restarting after the first error. Doubles for every error in a row.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub retry_initial_backoff: std::time::Duration,
+    /// Longest time to wait before restarting.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "30s")]
+    pub retry_max_backoff: std::time::Duration,
+    /// Consider the bot recovered if it ran for this long before failing again.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "5m")]
+    pub retry_reset_after: std::time::Duration,
+    /// Format of the log output.
+    #[clap(long, env, hide_env = true, value_enum, default_value = "pretty")]
+    pub log_format: util::LogFormat,
+    /// Write logs to this file instead of stdout.
+    #[clap(long, env, hide_env = true)]
+    pub log_file: Option<std::path::PathBuf>,
+    /// When to rotate the log file. Either `never`, `daily` or a size like `10MB`.
+    #[clap(long, env, hide_env = true, default_value = "never")]
+    pub log_rotation: util::LogRotation,
+    /// Amount of rotated log files to keep.
+    #[clap(long, env, hide_env = true, default_value = "5")]
+    pub log_max_files: usize,
+    /// Address to serve health endpoints and metrics on, e.g `0.0.0.0:8080`. Serves `/healthz`, `/readyz`, `/version` and `/metrics`.
+    #[clap(long, env, hide_env = true)]
+    pub http_listen: Option<std::net::SocketAddr>,
+    /// How long to wait for pending messages to be sent to discord when shutting down.
+    #[clap(long, env, hide_env = true, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub shutdown_timeout: std::time::Duration,
+    /// Delete the eventsub subscriptions of the session when shutting down.
+    #[clap(long, env, hide_env = true)]
+    pub delete_subscriptions_on_shutdown: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Work with the configuration
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Validate the configuration, resolve channels and test the webhooks without starting the bot
+    Check,
+}
+
+impl Opts {
+    /// Read the secrets given with the `--*-file` options.
+    pub fn read_secret_files(&mut self) -> eyre::Result<()> {
+        if let Some(ref path) = self.discord_webhook_file {
+            self.discord_webhook = Some(is_url(&read_secret_file(path)?)?);
+        }
+        if let Some(ref path) = self.access_token_file {
+            self.access_token = Some(is_token(&read_secret_file(path)?)?);
+        }
+        if let Some(ref path) = self.oauth2_service_key_file {
+            self.oauth2_service_key = Some(Secret(read_secret_file(path)?));
+        }
+        if let Some(ref path) = self.dcf_oauth_client_secret_file {
+            self.dcf_oauth_client_secret = Some(twitch_api::twitch_oauth2::ClientSecret::new(
+                read_secret_file(path)?,
+            ));
+        }
+        if let Some(ref path) = self.ops_webhook_file {
+            self.ops_webhook = Some(is_url(&read_secret_file(path)?)?);
+        }
+        Ok(())
+    }
+
+    /// URL to discord webhook, from `--discord-webhook` or `--discord-webhook-file`.
+    pub fn discord_webhook(&self) -> &Secret {
+        self.discord_webhook
+            .as_ref()
+            .expect("discord webhook should be set after reading secret files")
+    }
+}
+
+/// Read a secret from a file, ignoring surrounding whitespace like a trailing newline.
+fn read_secret_file(path: &std::path::Path) -> eyre::Result<String> {
+    std::fs::read_to_string(path)
+        .map(|s| s.trim().to_owned())
+        .wrap_err_with(|| format!("could not read secret file `{}`", path.display()))
+}
+
+pub fn is_url(s: &str) -> eyre::Result<Secret> {
+    url::Url::parse(s)?;
+    Ok(Secret(s.to_owned()))
+}
+
+pub fn is_token(s: &str) -> eyre::Result<Secret> {
+    if s.starts_with("oauth:") {
+        eyre::bail!("token should not have `oauth:` as a prefix")
+    }
+    if s.len() != 30 {
+        eyre::bail!("token needs to be 30 characters long")
+    }
+    Ok(Secret(s.to_owned()))
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Secret(String);
+
+impl Secret {
+    fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Secret {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer).map(Self)
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[secret]")
+    }
+}
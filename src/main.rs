@@ -1,248 +1,147 @@
-#[cfg(test)]
-pub mod ci;
-pub mod subscriber;
-pub mod util;
-pub mod webhook;
-
-use clap::{builder::ArgPredicate, ArgGroup, Parser};
+use clap::Parser;
 use eyre::WrapErr;
-
-#[derive(Parser, Debug)]
-#[clap(about, version, long_version = &**util::LONG_VERSION,
-    group = ArgGroup::new("token").multiple(false).required(false),
-    group = ArgGroup::new("service").multiple(true).requires("oauth2_service_url"),
-    group = ArgGroup::new("dcf_oauth").multiple(true).requires("dcf_oauth_client_id"),
-    group = ArgGroup::new("channel").multiple(true).required(false),
-)]
-pub struct Opts {
-    /// URL to discord webhook.
-    #[clap(long, env, hide_env = true, value_parser = url::Url::parse)]
-    pub discord_webhook: url::Url,
-    /// OAuth2 Access token
-    #[clap(long, env, hide_env = true, group = "token",
-        value_parser = is_token, required_unless_present_any = ["service", "dcf_oauth"]
-    )]
-    pub access_token: Option<Secret>,
-    /// Name of channel to monitor. If left out, defaults to owner of access token.
-    #[clap(long, env, hide_env = true, group = "channel")]
-    pub channel_login: Option<String>,
-    /// User ID of channel to monitor. If left out, defaults to owner of access token.
-    #[clap(long, env, hide_env = true, group = "channel")]
-    pub channel_id: Option<String>,
-    /// URL to service that provides OAuth2 token. Called on start and whenever the token needs to be refreshed.
-    ///
-    /// This application does not do any refreshing of tokens.
-    #[clap(long, env, hide_env = true, group = "service",
-        value_parser = url::Url::parse, required_unless_present_any = ["token", "dcf_oauth"]
-    )]
-    pub oauth2_service_url: Option<url::Url>,
-    /// Bearer key for authorizing on the OAuth2 service url.
-    #[clap(long, env, hide_env = true, group = "service")]
-    pub oauth2_service_key: Option<Secret>,
-    /// Grab token by pointer. See https://tools.ietf.org/html/rfc6901
-    #[clap(
-        long,
-        env,
-        hide_env = true,
-        group = "service",
-        default_value_if("oauth2_service_url", ArgPredicate::IsPresent, Some("/access_token"))
-    )]
-    pub oauth2_service_pointer: Option<String>,
-    /// Grab a new token from the OAuth2 service this many seconds before it actually expires. Default is 30 seconds
-    #[clap(
-        long,
-        env,
-        hide_env = true,
-        group = "service",
-        default_value_if("oauth2_service_url", ArgPredicate::IsPresent, Some("30"))
-    )]
-    pub oauth2_service_refresh: Option<u64>,
-    /// Client id to get a token. Stores the token data in the path specified by `--dcf-secret` (client id and optional secret is not stored)
-    #[clap(long, env, hide_env = true, group = "dcf_oauth", required_unless_present_any = ["access_token", "service"])]
-    pub dcf_oauth_client_id: Option<twitch_api::twitch_oauth2::ClientId>,
-    /// Client secret to get a token. Only needed for confidential applications.
-    #[clap(long, env, hide_env = true, group = "dcf_oauth")]
-    pub dcf_oauth_client_secret: Option<twitch_api::twitch_oauth2::ClientSecret>,
-    /// Path for storing DCF oauth.
-    #[clap(
-        long,
-        env,
-        hide_env = true,
-        group = "dcf_oauth",
-        default_value = "./.dcf_secret"
-    )]
-    pub dcf_secret_path: Option<std::path::PathBuf>,
-    ///
-    /// Name of channel bot.
-    #[clap(long, env, hide_env = true)]
-    pub channel_bot_name: Option<String>,
-    /// Report unrecoverable errors to the discord webhook instead of making the program exit.
-    #[clap(long, env, hide_env = true)]
-    pub discord_error_report: bool,
-}
-
-pub fn is_token(s: &str) -> eyre::Result<Secret> {
-    if s.starts_with("oauth:") {
-        eyre::bail!("token should not have `oauth:` as a prefix")
-    }
-    if s.len() != 30 {
-        eyre::bail!("token needs to be 30 characters long")
-    }
-    Ok(Secret(s.to_owned()))
-}
-
-#[derive(Clone)]
-pub struct Secret(String);
-
-impl Secret {
-    fn secret(&self) -> &str {
-        &self.0
-    }
-}
-
-impl std::str::FromStr for Secret {
-    type Err = std::convert::Infallible;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.to_string()))
-    }
-}
-
-impl std::fmt::Debug for Secret {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[secret]")
-    }
-}
+use twitch_discord_moderation::{
+    config, health, pipeline, report, server, shutdown, supervisor, util, Command, ConfigCommand,
+    Opts, Pipeline,
+};
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    use twitch_api::client::ClientDefault;
     let _ = dotenvy::dotenv().with_context(|| "couldn't load .env file"); //ignore error
-    let _ = util::build_logger();
 
-    let opts = Opts::parse();
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let config_file = match config::Config::path_from_args(args.clone()) {
+        Some(path) => config::Config::load(&path)?,
+        None => config::Config::default(),
+    };
+    let mut opts = Opts::parse_from(config_file.merge_args(args.clone())?);
+    config_file.validate(opts.log_file.as_deref())?;
+    opts.args = args;
+    opts.config_file = config_file;
+    opts.read_secret_files()?;
+    let _log_guard = util::build_logger(&opts).wrap_err("could not build logger")?;
     tracing::info!(
         "App started!\n{}",
         Opts::try_parse_from(["app", "--version"])
             .unwrap_err()
             .to_string()
     );
-    #[allow(unused_assignments)]
-    let (mut times, mut when, mut error, mut old_err) =
-        (0, std::time::Instant::now(), String::new(), String::new());
-    let product = format!(
-        "twitch_discord_moderation/{} (https://github.com/Emilgardis/twitch_discord_moderation)",
-        env!("CARGO_PKG_VERSION")
-    );
-    let client = reqwest::Client::default_client_with_name(Some(product.try_into()?))?;
+    let client = pipeline::default_client()?;
+    let health = std::sync::Arc::new(health::Health::default());
+    if let Some(Command::Config(ConfigCommand::Check)) = opts.command {
+        return config::check(&client, &opts, health).await;
+    }
+    let shutdown = shutdown::Shutdown::new();
+    shutdown.listen_for_signals();
+    if let Some(addr) = opts.http_listen {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .wrap_err_with(|| format!("could not listen on `{addr}`"))?;
+        let health = health.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(listener, health).await {
+                tracing::error!(error = ?e, "health server stopped");
+            }
+        });
+    }
 
+    let reporter = report::Reporter::from_opts(&client, &opts);
+    let mut supervisor = supervisor::Supervisor::new(supervisor::RetryPolicy::from_opts(&opts));
+    let mut pipeline = Pipeline::builder(opts)
+        .client(client.clone())
+        .health(health.clone())
+        .build()?;
+    pipeline.reload().listen();
     let err = loop {
-        match run(&client, &opts).await {
-            Ok(_) => {}
-            Err(err) => {
-                error = "".to_string();
-                for err in <eyre::Report>::chain(&err) {
-                    error.push_str(&format!("> {err}\n"));
-                }
-                if when.elapsed() > std::time::Duration::from_secs(5 * 60) {
-                    times = 0;
-                }
-                if times == 0 {
-                    old_err = error.clone();
-                }
-                times += 1;
-                if when.elapsed() < std::time::Duration::from_secs(2) {
-                    break err;
-                }
-                if times >= 10 {
-                    break err;
-                } else if times == 1 {
-                    // don't sleep on the first error
-                } else {
-                    let backoff = 2u64.pow(times as u32).min(30);
-                    tracing::warn!(
-                        "Error occurred, sleeping for {backoff} seconds. Error: {error}"
-                    );
-                    tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
-                    when = std::time::Instant::now();
-                }
-                tracing::error!("An error occurred.");
-                for err in <eyre::Report>::chain(&err) {
-                    tracing::error!(Error = %err);
-                }
+        let started = std::time::Instant::now();
+        // pick up options reloaded since the last restart
+        let opts = pipeline.reload().current();
+        let result = tokio::select! {
+            r = pipeline.run(&shutdown) => r,
+            _ = async {
+                shutdown.wait().await;
+                tokio::time::sleep(opts.shutdown_timeout).await;
+            } => {
+                tracing::warn!(
+                    "shutdown took longer than {}, exiting",
+                    humantime::format_duration(opts.shutdown_timeout)
+                );
+                Ok(())
             }
+            _ = report_recovery(reporter.as_ref(), &health, supervisor.state()) => unreachable!(),
+        };
+        if shutdown.is_triggered() {
+            tracing::info!("shutdown complete");
+            return result;
         }
-    };
-    tracing::error!("An error occurred.");
-    for err in <eyre::Report>::chain(&err) {
-        tracing::error!(Error = %err);
-    }
-    if opts.discord_error_report {
-        let first_error = if error != old_err && !old_err.is_empty() {
-            format!("Error 1:\n```\n{old_err}\n```\nError 2:\n")
-        } else {
-            "".to_string()
+        let err = match result {
+            Ok(_) => continue,
+            Err(err) => err,
         };
-        let many = if times > 0 {
-            format!(" (x{})", times + 1)
-        } else {
-            "".to_string()
+        // the connection is gone
+        health.set_session(None);
+        tracing::error!("An error occurred.");
+        for err in <eyre::Report>::chain(&err) {
+            tracing::error!(Error = %err);
+        }
+        let decision = supervisor.on_error(&err, started.elapsed());
+        let state = supervisor.state();
+        let backoff = match (decision, &reporter) {
+            (supervisor::Decision::Retry(backoff), _) => {
+                tracing::warn!(
+                    attempt = state.attempt,
+                    restarts = state.restarts,
+                    class = ?state.last_class,
+                    "Error occurred, restarting in {}",
+                    humantime::format_duration(std::time::Duration::from_millis(
+                        backoff.as_millis() as u64
+                    ))
+                );
+                if let Some(ref reporter) = reporter {
+                    reporter.error(report::Level::Warning, &err, state).await;
+                }
+                backoff
+            }
+            // keep retrying when errors are reported, the cause might clear
+            (supervisor::Decision::GiveUp, Some(reporter)) => {
+                tracing::error!(
+                    attempt = state.attempt,
+                    restarts = state.restarts,
+                    class = ?state.last_class,
+                    "Can not recover from error, retrying in {}",
+                    humantime::format_duration(opts.retry_max_backoff)
+                );
+                reporter.error(report::Level::Error, &err, state).await;
+                opts.retry_max_backoff
+            }
+            (supervisor::Decision::GiveUp, None) => break err,
         };
-        let http = serenity::http::HttpBuilder::without_token()
-            .client(client.clone())
-            .build();
-        let webhook =
-            serenity::model::webhook::Webhook::from_url(&http, opts.discord_webhook.as_str())
-                .await?;
-        let message = serenity::all::ExecuteWebhook::new()
-            .username("twitch_moderation")
-            .content(format!(
-                "The bot crashed{many}. The bot has stopped\n{first_error}```\n{error}```"
-            ));
-        let e = webhook.execute(&http, false, message).await;
-        // message was sent ok. stall the program to prevent reporting again
-        if e.is_ok() {
-            tracing::info!(
-                "Error report sent to discord. Stalling the program to prevent it from exiting."
-            );
-            futures::future::pending::<()>().await;
-            unreachable!();
-        } else {
-            tracing::error!(
-                "Error report failed to send to discord. Error: {}",
-                e.unwrap_err()
-            );
-            // some sleeping so that we don't hit the resources again immediately if the program gets restarted
-            // and the error is still there
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.wait() => return Ok(()),
         }
-    }
-    return Err(err);
+    };
+    let state = supervisor.state();
+    tracing::error!(
+        attempt = state.attempt,
+        restarts = state.restarts,
+        class = ?state.last_class,
+        "Giving up."
+    );
+    Err(err)
 }
 
-pub async fn run(client: &reqwest::Client, opts: &Opts) -> eyre::Result<()> {
-    let subscriber = subscriber::Subscriber::new(client, opts)
-        .await
-        .context("could not construct subscriber")?;
-    let webhook = webhook::Webhook::new(client, subscriber.channel_login.clone(), opts).await?;
-    let recv = subscriber.channel.subscribe();
-    tracing::debug!("entering main block");
-    tokio::select!(
-    r = subscriber.run(opts) => {
-        tracing::warn!(message = "subscriber exited early", result = ?r);
-        if r.is_err() {
-            r.with_context(|| "subscriber error")?
-        } else {
-            eyre::bail!("subscriber returned early when it should not have")
+/// Report that the bot has recovered once it is ready again, then never return.
+async fn report_recovery(
+    reporter: Option<&report::Reporter>,
+    health: &health::Health,
+    state: &supervisor::RestartState,
+) {
+    if let Some(reporter) = reporter {
+        while !health.is_ready() {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
-    },
-    r = webhook.run(recv) => {
-        tracing::warn!(message = "webhook exited early", result = ?r);
-        if r.is_err() {
-            r.with_context(|| "webhook error")?
-        } else {
-            eyre::bail!("webhook returned early when it should not have")
-        }
-    });
-    Ok(())
+        reporter.recovered(state).await;
+    }
+    futures::future::pending::<()>().await
 }
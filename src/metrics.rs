@@ -0,0 +1,124 @@
+//! Prometheus metrics, served on `/metrics`
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_int_counter, register_int_counter_vec, register_int_gauge, IntCounter, IntCounterVec,
+    IntGauge,
+};
+
+/// Events received from eventsub, by action kind and channel.
+pub static EVENTS_RECEIVED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "twitch_discord_moderation_events_received_total",
+        "Events received from twitch",
+        &["kind", "channel"]
+    )
+    .unwrap()
+});
+
+/// Deliveries to discord, by result. Either `success` or `failure`.
+pub static DISCORD_DELIVERIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "twitch_discord_moderation_discord_deliveries_total",
+        "Messages delivered to discord",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// Times a post to discord waited for a rate limit. Serenity waits and retries, so these aren't failed deliveries.
+pub static DISCORD_RATE_LIMITS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "twitch_discord_moderation_discord_rate_limits_total",
+        "Times a post to discord waited for a rate limit"
+    )
+    .unwrap()
+});
+
+/// Deliveries to the sinks in the config file, by sink and result. Either `success` or `failure`.
+pub static SINK_DELIVERIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "twitch_discord_moderation_sink_deliveries_total",
+        "Events delivered to sinks other than discord",
+        &["sink", "result"]
+    )
+    .unwrap()
+});
+
+/// Websocket reconnects, by cause. Either `keepalive_timeout`, `stream_ended` or `protocol_reset`.
+pub static WEBSOCKET_RECONNECTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "twitch_discord_moderation_websocket_reconnects_total",
+        "Reconnects of the eventsub websocket",
+        &["cause"]
+    )
+    .unwrap()
+});
+
+/// When the token expires, in seconds since the unix epoch. Set whenever the token is refreshed.
+pub static TOKEN_EXPIRES_AT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "twitch_discord_moderation_token_expiry_timestamp_seconds",
+        "Unix time when the twitch token expires"
+    )
+    .unwrap()
+});
+
+/// Times the bot has been restarted after an error.
+pub static RESTARTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "twitch_discord_moderation_restarts_total",
+        "Times the bot has restarted after an error"
+    )
+    .unwrap()
+});
+
+/// Errors that stopped the bot, by class. Either `fatal` or `transient`.
+pub static ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "twitch_discord_moderation_errors_total",
+        "Errors that stopped the bot",
+        &["class"]
+    )
+    .unwrap()
+});
+
+/// Errors in a row, without the bot running long enough to be considered recovered.
+pub static CONSECUTIVE_ERRORS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "twitch_discord_moderation_consecutive_errors",
+        "Errors in a row without the bot recovering"
+    )
+    .unwrap()
+});
+
+/// Register all metrics, so that they show up before anything has happened.
+pub fn init() {
+    Lazy::force(&EVENTS_RECEIVED);
+    Lazy::force(&DISCORD_DELIVERIES);
+    Lazy::force(&DISCORD_RATE_LIMITS);
+    Lazy::force(&SINK_DELIVERIES);
+    Lazy::force(&WEBSOCKET_RECONNECTS);
+    Lazy::force(&TOKEN_EXPIRES_AT);
+    Lazy::force(&RESTARTS);
+    Lazy::force(&ERRORS);
+    Lazy::force(&CONSECUTIVE_ERRORS);
+}
+
+/// Record the result of a delivery to discord.
+pub fn record_delivery<T>(result: &Result<T, serenity::Error>) {
+    let label = match result {
+        Ok(_) => "success",
+        Err(_) => "failure",
+    };
+    DISCORD_DELIVERIES.with_label_values(&[label]).inc();
+}
+
+/// Encode all metrics in the prometheus text format.
+pub fn gather() -> Result<String, eyre::Report> {
+    use prometheus::Encoder;
+
+    let mut buffer = vec![];
+    prometheus::TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
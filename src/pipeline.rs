@@ -0,0 +1,276 @@
+//! Running the bot, or embedding it in another program.
+
+use std::sync::Arc;
+
+use eyre::WrapErr;
+use futures::future::BoxFuture;
+use tokio::sync::{mpsc, watch};
+
+use crate::event::ModerationEvent;
+use crate::sink::Sink;
+
+/// [`Sink`] as a trait object.
+trait DynSink: Send + Sync {
+    fn run<'a>(
+        &'a mut self,
+        recv: mpsc::Receiver<ModerationEvent>,
+        reload: watch::Receiver<Arc<crate::Opts>>,
+        shutdown: &'a crate::shutdown::Shutdown,
+    ) -> BoxFuture<'a, Result<(), eyre::Report>>;
+}
+
+impl<S: Sink + Sync> DynSink for S {
+    fn run<'a>(
+        &'a mut self,
+        recv: mpsc::Receiver<ModerationEvent>,
+        reload: watch::Receiver<Arc<crate::Opts>>,
+        shutdown: &'a crate::shutdown::Shutdown,
+    ) -> BoxFuture<'a, Result<(), eyre::Report>> {
+        Box::pin(crate::sink::run(self, recv, reload, shutdown))
+    }
+}
+
+/// A [`Sink`] calling a function for every event, see [`PipelineBuilder::handler`].
+struct Handler<F>(F);
+
+impl<F, Fut> Sink for Handler<F>
+where
+    F: Fn(ModerationEvent) -> Fut + Send + Sync,
+    Fut: std::future::Future<Output = Result<(), eyre::Report>> + Send,
+{
+    fn name(&self) -> &'static str {
+        "handler"
+    }
+
+    async fn send(&self, event: &ModerationEvent) -> Result<(), eyre::Report> {
+        (self.0)(event.clone()).await
+    }
+}
+
+/// A client identifying as this crate.
+pub fn default_client() -> Result<reqwest::Client, eyre::Report> {
+    use twitch_api::client::ClientDefault;
+
+    let product = format!(
+        "twitch_discord_moderation/{} (https://github.com/Emilgardis/twitch_discord_moderation)",
+        env!("CARGO_PKG_VERSION")
+    );
+    Ok(reqwest::Client::default_client_with_name(Some(
+        product.try_into()?,
+    ))?)
+}
+
+/// Builder for [`Pipeline`].
+pub struct PipelineBuilder {
+    opts: crate::Opts,
+    client: Option<reqwest::Client>,
+    health: Option<Arc<crate::health::Health>>,
+    sinks: Vec<Box<dyn DynSink>>,
+    discord: bool,
+    configured_sinks: bool,
+}
+
+impl PipelineBuilder {
+    /// Client to use for twitch and the sinks. Defaults to a client identifying as this crate.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Health of the pipeline, e.g to serve it with [`crate::server::serve`].
+    pub fn health(mut self, health: Arc<crate::health::Health>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Call `handler` for every event. An error stops the pipeline.
+    pub fn handler<F, Fut>(self, handler: F) -> Self
+    where
+        F: Fn(ModerationEvent) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<(), eyre::Report>> + Send + 'static,
+    {
+        self.sink(Handler(handler))
+    }
+
+    /// Deliver every event to `sink`, in addition to discord.
+    pub fn sink(mut self, sink: impl Sink + Sync + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Don't post to `--discord-webhook`, only deliver events to the sinks and handlers.
+    pub fn without_discord(mut self) -> Self {
+        self.discord = false;
+        self
+    }
+
+    /// Don't run the `sinks` in the config file.
+    pub fn without_configured_sinks(mut self) -> Self {
+        self.configured_sinks = false;
+        self
+    }
+
+    /// Read the secret files of the options and check that discord can be posted to, unless [`Self::without_discord`] was used.
+    pub fn build(mut self) -> Result<Pipeline, eyre::Report> {
+        self.opts.read_secret_files()?;
+        if self.discord && self.opts.discord_webhook.is_none() {
+            eyre::bail!("no `--discord-webhook` is set, it's needed unless the pipeline is built without discord");
+        }
+        let client = match self.client {
+            Some(client) => client,
+            None => default_client()?,
+        };
+        Ok(Pipeline {
+            client,
+            reload: crate::reload::Reload::new(self.opts),
+            health: self.health.unwrap_or_default(),
+            sinks: self.sinks,
+            discord: self.discord,
+            configured_sinks: self.configured_sinks,
+        })
+    }
+}
+
+/// Events from the eventsub websocket delivered to discord and other sinks.
+///
+/// ```no_run
+/// # async fn example() -> eyre::Result<()> {
+/// use clap::Parser;
+/// use twitch_discord_moderation::{Opts, Pipeline};
+///
+/// let opts = Opts::parse();
+/// let mut pipeline = Pipeline::builder(opts)
+///     .handler(|event| async move {
+///         if let Some(ref moderator) = event.moderator {
+///             println!("{} did {} in {}", moderator.login, event.kind, event.channel.login);
+///         }
+///         Ok(())
+///     })
+///     .build()?;
+/// pipeline.run(&Default::default()).await
+/// # }
+/// ```
+pub struct Pipeline {
+    client: reqwest::Client,
+    reload: crate::reload::Reload,
+    health: Arc<crate::health::Health>,
+    sinks: Vec<Box<dyn DynSink>>,
+    discord: bool,
+    configured_sinks: bool,
+}
+
+impl Pipeline {
+    pub fn builder(opts: crate::Opts) -> PipelineBuilder {
+        PipelineBuilder {
+            opts,
+            client: None,
+            health: None,
+            sinks: vec![],
+            discord: true,
+            configured_sinks: true,
+        }
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub fn health(&self) -> &Arc<crate::health::Health> {
+        &self.health
+    }
+
+    /// Current options, and reloading them. Call [`crate::reload::Reload::listen`] to reload on changes to the config file.
+    pub fn reload(&self) -> &crate::reload::Reload {
+        &self.reload
+    }
+
+    /// Connect to twitch and deliver events until `shutdown` or an error.
+    ///
+    /// Errors are not retried, run the pipeline again to reconnect.
+    pub async fn run(&mut self, shutdown: &crate::shutdown::Shutdown) -> Result<(), eyre::Report> {
+        let Pipeline {
+            client,
+            reload,
+            health,
+            sinks,
+            discord,
+            configured_sinks,
+        } = self;
+        let opts = reload.current();
+        let subscriber = crate::subscriber::Subscriber::new(client, &opts, health.clone())
+            .await
+            .context("could not construct subscriber")?;
+        let mut webhook = if *discord {
+            Some(crate::webhook::Webhook::new(client, &opts, health.clone()).await?)
+        } else {
+            None
+        };
+        // subscribe before the subscriber starts sending
+        let recv = discord.then(|| subscriber.channel.subscribe());
+        let sinks_run =
+            futures::future::try_join_all(sinks.iter_mut().map(|sink| {
+                sink.run(subscriber.channel.subscribe(), reload.subscribe(), shutdown)
+            }));
+        tracing::debug!("entering main block");
+        let subscriber_run = subscriber.run(reload.subscribe(), shutdown);
+        let webhook_run = async {
+            tokio::try_join!(
+                async {
+                    match (webhook.as_mut(), recv) {
+                        (Some(webhook), Some(recv)) => {
+                            crate::sink::run(webhook, recv, reload.subscribe(), shutdown).await
+                        }
+                        _ => {
+                            shutdown.wait().await;
+                            Ok(())
+                        }
+                    }
+                },
+                async {
+                    if *configured_sinks {
+                        crate::sink::run_configured(
+                            client,
+                            &subscriber.channel,
+                            reload.subscribe(),
+                            shutdown,
+                        )
+                        .await
+                    } else {
+                        shutdown.wait().await;
+                        Ok(())
+                    }
+                },
+                sinks_run,
+            )
+            .map(|_| ())
+        };
+        tokio::pin!(subscriber_run, webhook_run);
+        tokio::select!(
+        r = &mut subscriber_run => {
+            if shutdown.is_triggered() {
+                // the subscriber has stopped, let the webhook send what it has left
+                r.with_context(|| "subscriber error")?;
+                return webhook_run.await.with_context(|| "webhook error");
+            }
+            tracing::warn!(message = "subscriber exited early", result = ?r);
+            if r.is_err() {
+                r.with_context(|| "subscriber error")?
+            } else {
+                eyre::bail!("subscriber returned early when it should not have")
+            }
+        },
+        r = &mut webhook_run => {
+            if shutdown.is_triggered() {
+                r.with_context(|| "webhook error")?;
+                return subscriber_run.await.with_context(|| "subscriber error");
+            }
+            tracing::warn!(message = "webhook exited early", result = ?r);
+            if r.is_err() {
+                r.with_context(|| "webhook error")?
+            } else {
+                eyre::bail!("webhook returned early when it should not have")
+            }
+        });
+        Ok(())
+    }
+}
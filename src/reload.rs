@@ -0,0 +1,93 @@
+//! Reloading the config file while running, on SIGHUP or when the file changes.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Holds the current options, and tells subscribers when they change.
+#[derive(Debug, Clone)]
+pub struct Reload {
+    sender: Arc<watch::Sender<Arc<crate::Opts>>>,
+}
+
+impl Reload {
+    pub fn new(opts: crate::Opts) -> Self {
+        Self {
+            sender: Arc::new(watch::channel(Arc::new(opts)).0),
+        }
+    }
+
+    /// The options currently in use.
+    pub fn current(&self) -> Arc<crate::Opts> {
+        self.sender.borrow().clone()
+    }
+
+    /// Get notified about new options. The current options are marked as seen.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<crate::Opts>> {
+        self.sender.subscribe()
+    }
+
+    /// Read the config file again and send the new options to subscribers.
+    pub fn reload(&self) -> Result<(), eyre::Report> {
+        let current = self.current();
+        let Some(ref path) = current.config else {
+            tracing::info!("no config file given, nothing to reload");
+            return Ok(());
+        };
+        let opts = crate::config::Config::reload(&current)?;
+        tracing::info!(config = %path.display(), "reloaded config file");
+        self.sender.send_replace(Arc::new(opts));
+        Ok(())
+    }
+
+    /// Reload on SIGHUP, and when the config file is modified if `--config-watch-interval` is not zero.
+    pub fn listen(&self) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = this.listen_inner().await {
+                tracing::error!(error = ?e, "could not listen for config changes");
+            }
+        });
+    }
+
+    async fn listen_inner(&self) -> Result<(), eyre::Report> {
+        let opts = self.current();
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        let mut interval = (!opts.config_watch_interval.is_zero() && opts.config.is_some())
+            .then(|| tokio::time::interval(opts.config_watch_interval));
+        let mut modified = opts.config.as_deref().and_then(modified_at);
+        loop {
+            tokio::select! {
+                _ = async {
+                    #[cfg(unix)]
+                    hangup.recv().await;
+                    #[cfg(not(unix))]
+                    futures::future::pending::<()>().await;
+                } => {
+                    tracing::info!("received SIGHUP, reloading config");
+                }
+                _ = async {
+                    match interval {
+                        Some(ref mut interval) => interval.tick().await,
+                        None => futures::future::pending().await,
+                    }
+                } => {
+                    let now = self.current().config.as_deref().and_then(modified_at);
+                    if now == modified {
+                        continue;
+                    }
+                    tracing::info!("config file was modified, reloading config");
+                }
+            }
+            modified = self.current().config.as_deref().and_then(modified_at);
+            if let Err(e) = self.reload() {
+                tracing::error!(error = ?e, "could not reload config, keeping the current one");
+            }
+        }
+    }
+}
+
+fn modified_at(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
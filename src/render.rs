@@ -0,0 +1,675 @@
+//! Rendering moderator actions as text for the different sinks.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use twitch_api::types;
+
+use crate::event::{ModerationEvent, ShieldSummary, StreamSummary};
+use crate::util::Sanitize;
+
+/// Markup language of a sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Discord markdown
+    Discord,
+    /// Slack mrkdwn
+    Slack,
+    /// HTML, as used by matrix
+    Html,
+    Plain,
+}
+
+impl Markup {
+    /// Escape text from twitch so that it isn't interpreted as markup.
+    pub fn escape<'a>(self, s: &'a str) -> Cow<'a, str> {
+        match self {
+            Markup::Discord => match s.sanitize() {
+                Cow::Borrowed(_) => Cow::Borrowed(s),
+                Cow::Owned(s) => Cow::Owned(s),
+            },
+            Markup::Slack if s.contains(['&', '<', '>']) => Cow::Owned(
+                s.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            ),
+            Markup::Html if s.contains(['&', '<', '>', '"']) => Cow::Owned(
+                s.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace('"', "&quot;"),
+            ),
+            _ => Cow::Borrowed(s),
+        }
+    }
+
+    pub fn emphasis(self, s: &str) -> String {
+        match self {
+            Markup::Discord => format!("*{s}*"),
+            Markup::Slack => format!("_{s}_"),
+            Markup::Html => format!("<em>{s}</em>"),
+            Markup::Plain => s.to_owned(),
+        }
+    }
+
+    /// Hide text until clicked, where supported
+    pub fn spoiler(self, s: &str) -> String {
+        match self {
+            Markup::Discord => format!("||{s}||"),
+            Markup::Html => format!("<span data-mx-spoiler>{s}</span>"),
+            Markup::Slack | Markup::Plain => s.to_owned(),
+        }
+    }
+
+    /// A heading that stands out, where supported
+    pub fn heading(self, s: &str) -> String {
+        match self {
+            Markup::Discord => format!("# {s}"),
+            Markup::Slack => format!("*{s}*"),
+            Markup::Html => format!("<h3>{s}</h3>"),
+            Markup::Plain => s.to_uppercase(),
+        }
+    }
+
+    /// Link to a url, without a preview where supported
+    pub fn link(self, text: &str, url: &str) -> String {
+        match self {
+            Markup::Discord => format!("[{text}](<{url}>)"),
+            Markup::Slack => format!("<{url}|{text}>"),
+            Markup::Html => format!("<a href=\"{url}\">{text}</a>"),
+            Markup::Plain => format!("{text}: {url}"),
+        }
+    }
+
+    /// Link to the viewer card of a user in a channel
+    pub fn usercard(self, channel_login: &str, user_login: &str) -> String {
+        match self {
+            Markup::Discord => format!(
+                "[{1}](<https://www.twitch.tv/popout/{0}/viewercard/{1}?popout=>)",
+                channel_login.sanitize(),
+                user_login
+            ),
+            Markup::Slack => format!(
+                "<https://www.twitch.tv/popout/{channel_login}/viewercard/{user_login}?popout=|{user_login}>"
+            ),
+            Markup::Html => format!(
+                "<a href=\"https://www.twitch.tv/popout/{channel_login}/viewercard/{user_login}?popout=\">{user_login}</a>"
+            ),
+            Markup::Plain => user_login.to_owned(),
+        }
+    }
+
+    /// A point in time in RFC 3339, shown in the timezone of the reader where supported
+    pub fn time(self, timestamp: &str) -> String {
+        match (self, types::Timestamp::new(timestamp.to_owned())) {
+            (Markup::Discord, Ok(timestamp)) => {
+                format!("<t:{}>", timestamp.to_utc().unix_timestamp())
+            }
+            _ => timestamp.to_owned(),
+        }
+    }
+
+    /// How long ago a point in time in RFC 3339 was, where supported
+    pub fn time_ago(self, timestamp: &str) -> String {
+        match (self, types::Timestamp::new(timestamp.to_owned())) {
+            (Markup::Discord, Ok(timestamp)) => {
+                format!("<t:{}:R>", timestamp.to_utc().unix_timestamp())
+            }
+            _ => timestamp.to_owned(),
+        }
+    }
+
+    fn header(self, emoji: &str) -> String {
+        let title = match self {
+            Markup::Discord | Markup::Slack => "_Twitch Moderation_".to_owned(),
+            _ => self.emphasis("Twitch Moderation"),
+        };
+        format!("{emoji}{title} |")
+    }
+}
+
+/// Render a moderator action, or `None` if it should not be posted.
+pub fn moderator_action(event: &ModerationEvent, markup: Markup) -> Option<String> {
+    let m = match event.moderator {
+        Some(ref moderator) => markup.emphasis(&moderator.login),
+        None => "someone".to_owned(),
+    };
+    let (usercard, user) = match event.target {
+        Some(ref target) => {
+            let usercard = markup.usercard(&event.channel.login, &target.login);
+            let user = markup.emphasis(&format!("{usercard}:{}", target.id));
+            (usercard, user)
+        }
+        None => Default::default(),
+    };
+    let reason = match event.reason {
+        Some(ref reason) => format!("\nreason: {}", markup.escape(reason)),
+        None => "".to_owned(),
+    };
+    let mut message = match event.kind.as_str() {
+        // translation of the old commented code to more modern code and using eventsub instead of pubsub
+        "delete" => format!(
+            "{h}\n{m}: /delete {usercard} {message_body}\n{user} message deleted",
+            h = markup.header("❌"),
+            message_body =
+                markup.spoiler(&markup.escape(event.message.as_deref().unwrap_or_default())),
+        ),
+        "timeout" => format!(
+            // eventsub gives expires_at as a timestamp, discord shows it in the timezone of the reader
+            "{h}\n{m}: /timeout {usercard}\n{user} has been timed out until {expires}{reason}",
+            h = markup.header("🔨"),
+            expires = markup.time(event.expires_at.as_deref().unwrap_or_default()),
+        ),
+        "untimeout" => format!(
+            "{h}\n{m}: /untimeout {usercard}\n{user} is no longer timed out",
+            h = markup.header("🔨"),
+        ),
+        "ban" => format!(
+            "{h}\n{m}: /ban {usercard}\n{user} is now banned{reason}",
+            h = markup.header("🏝️"),
+        ),
+        "unban" => format!(
+            "{h}\n{m}: /unban {usercard}\n{user} is no longer banned",
+            h = markup.header("🏝️"),
+        ),
+        "followers" => format!(
+            "{h}\n{m}: /followers {minutes}m\nFollowers-only mode is now enabled for {minutes} minutes",
+            h = markup.header("🔒"),
+            minutes = event.duration_seconds.unwrap_or_default() / 60,
+        ),
+        "slow" => format!(
+            "{h}\n{m}: /slow {seconds}s\nSlow mode is now enabled with {seconds} seconds",
+            h = markup.header("🔒"),
+            seconds = event.duration_seconds.unwrap_or_default(),
+        ),
+        "vip" => format!(
+            "{h}\n{m}: /vip {usercard}\n{user} is now a VIP",
+            h = markup.header("🔨"),
+        ),
+        "unvip" => format!(
+            "{h}\n{m}: /unvip {usercard}\n{user} is no longer a VIP",
+            h = markup.header("🔨"),
+        ),
+        "mod" => format!(
+            "{h}\n{m}: /mod {usercard}\n{user} is now a moderator",
+            h = markup.header("🔨"),
+        ),
+        "unmod" => format!(
+            "{h}\n{m}: /unmod {usercard}\n{user} is no longer a moderator",
+            h = markup.header("🔨"),
+        ),
+        "raid" => format!(
+            "{h}\n{m}: /raid {usercard} {viewer_count}\n{user} is now being raided",
+            h = markup.header("🔨"),
+            viewer_count = event.viewer_count.unwrap_or_default(),
+        ),
+        "unraid" => format!(
+            "{h}\n{m}: /unraid {usercard}\n{user} raid was canceled",
+            h = markup.header("🔨"),
+        ),
+        "approve_unban_request" => format!(
+            "{h}\n{m}: /approve {usercard} : {moderator_message}\n{user} unban was approved",
+            h = markup.header("🔨"),
+            moderator_message = markup.escape(event.reason.as_deref().unwrap_or_default()),
+        ),
+        "deny_unban_request" => format!(
+            "{h}\n{m}: /deny {usercard} : {moderator_message}\n{user} unban was denied",
+            h = markup.header("🔨"),
+            moderator_message = markup.escape(event.reason.as_deref().unwrap_or_default()),
+        ),
+        "unban_request" => format!(
+            "{h}\n{user} requests to be unbanned: {text}{ban}",
+            h = markup.header("📨"),
+            text = markup.escape(event.message.as_deref().unwrap_or_default()),
+            ban = match event.ban {
+                Some(ref ban) => format!(
+                    "\nbanned {at} by {by}{reason}",
+                    at = markup.time_ago(&ban.created_at),
+                    by = markup.emphasis(&ban.moderator.login),
+                    reason = if ban.reason.is_empty() {
+                        "".to_owned()
+                    } else {
+                        format!(", reason: {}", markup.escape(&ban.reason))
+                    },
+                ),
+                None => "".to_owned(),
+            },
+        ),
+        "unban_request_resolve" => match event.status.as_deref() {
+            Some("canceled") => format!(
+                "{h}\n{user} canceled their unban request",
+                h = markup.header("📨"),
+            ),
+            status => format!(
+                "{h}\n{m} {status} the unban request of {usercard}{response}",
+                h = markup.header("📨"),
+                status = status.unwrap_or("resolved"),
+                response = match event.reason {
+                    Some(ref response) => format!("\nresponse: {}", markup.escape(response)),
+                    None => "".to_owned(),
+                },
+            ),
+        },
+        "suspicious_user_update" => match event.status.as_deref() {
+            Some("active_monitoring") => format!(
+                "{h}\n{m}: /monitor {usercard}\n{user} is now monitored",
+                h = markup.header("👀"),
+            ),
+            Some("restricted") => format!(
+                "{h}\n{m}: /restrict {usercard}\n{user} is now restricted, their messages are only shown to moderators",
+                h = markup.header("👀"),
+            ),
+            _ => format!(
+                "{h}\n{m}: /unmonitor {usercard}\n{user} is no longer monitored or restricted",
+                h = markup.header("👀"),
+            ),
+        },
+        "suspicious_user_message" => {
+            let mut message = format!(
+                "{h}\n{status} user {user} sent: {text}",
+                h = markup.header("👀"),
+                status = match event.status.as_deref() {
+                    Some("restricted") => "restricted",
+                    _ => "monitored",
+                },
+                text = markup.spoiler(&markup.escape(event.message.as_deref().unwrap_or_default())),
+            );
+            if let Some(evasion @ ("possible" | "likely")) = event.ban_evasion.as_deref() {
+                message.push_str(&format!("\nban evasion: {evasion}"));
+            }
+            if !event.shared_ban_channels.is_empty() {
+                let channels = event
+                    .shared_ban_channels
+                    .iter()
+                    .map(|c| {
+                        if c.login.is_empty() {
+                            c.id.clone()
+                        } else {
+                            markup.escape(&c.login).into_owned()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                message.push_str(&format!("\nalso banned in: {}", channels.join(", ")));
+            }
+            message
+        }
+        "shield_mode_begin" => format!(
+            "{h}\n{m}: /shield\n{heading}",
+            h = markup.header("🛡️"),
+            heading = markup.heading("🛡️ Shield Mode is now on"),
+        ),
+        "shield_mode_end" => match event.shield_summary {
+            Some(ref summary) => format!(
+                "{h}\n{m}: /shieldoff\nShield Mode is now off\n{summary}",
+                h = markup.header("🛡️"),
+                summary = shield_summary(markup, summary, &event.timestamp),
+            ),
+            None => format!(
+                "{h}\n{m}: /shieldoff\nShield Mode is now off",
+                h = markup.header("🛡️"),
+            ),
+        },
+        "warning_acknowledge" => format!(
+            "{h}\n{user} acknowledged their warning",
+            h = markup.header("🔨"),
+        ),
+        "warning_ignored" => format!(
+            "{h}\n{user} is chatting without acknowledging their warning: {text}",
+            h = markup.header("⚠️"),
+            text = markup.spoiler(&markup.escape(event.message.as_deref().unwrap_or_default())),
+        ),
+        "chat_settings_update" => match (&event.previous_chat_settings, &event.chat_settings) {
+            (Some(previous), Some(settings)) => format!(
+                "{h}\nsettings changed: {changes}",
+                h = markup.header("🔒"),
+                changes = crate::settings::changes(previous, settings).join(", "),
+            ),
+            (None, Some(settings)) => format!(
+                "{h}\nsettings are now: {settings}",
+                h = markup.header("🔒"),
+                settings = crate::settings::describe(settings)
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            _ => return None,
+        },
+        "moderator_add" => format!("{h}\n{user} is now a moderator", h = markup.header("🔨")),
+        "moderator_remove" => format!(
+            "{h}\n{user} is no longer a moderator",
+            h = markup.header("🔨"),
+        ),
+        "vip_add" => format!("{h}\n{user} is now a VIP", h = markup.header("🔨")),
+        "vip_remove" => format!("{h}\n{user} is no longer a VIP", h = markup.header("🔨")),
+        "roster" => format!(
+            "{h}\nmoderators: {moderators}\nVIPs: {vips}",
+            h = markup.header("📋"),
+            moderators = members(markup, &event.moderators),
+            vips = members(markup, &event.vips),
+        ),
+        "stream_online" => format!("{h}\nthe stream is now live", h = markup.header("🔴")),
+        "stream_offline" => match event.stream_summary {
+            Some(ref summary) => format!(
+                "{h}\nthe stream is now offline\n{summary}",
+                h = markup.header("🔴"),
+                summary = stream_summary(markup, summary, &event.timestamp),
+            ),
+            None => format!("{h}\nthe stream is now offline", h = markup.header("🔴")),
+        },
+        kind if kind.starts_with("shared_chat_") => {
+            // NOP
+            return None;
+        }
+        "emoteonly" => format!("{h}\n{m}: /emoteonly\nEmote-only mode is now enabled", h = markup.header("🔒")),
+        "emoteonlyoff" => format!("{h}\n{m}: /emoteonlyoff\nEmote-only mode is now disabled", h = markup.header("🔒")),
+        "followersoff" => format!("{h}\n{m}: /followersoff\nFollowers-only mode is now disabled", h = markup.header("🔒")),
+        "uniquechat" => format!("{h}\n{m}: /uniquechat\nUnique chat is now enabled", h = markup.header("🔒")),
+        "uniquechatoff" => format!("{h}\n{m}: /uniquechatoff\nUnique chat is now disabled", h = markup.header("🔒")),
+        "slowoff" => format!("{h}\n{m}: /slowoff\nSlow mode is now disabled", h = markup.header("🔒")),
+        "subscribers" => format!("{h}\n{m}: /subscribers\nSubscribers-only mode is now enabled", h = markup.header("🔒")),
+        "subscribersoff" => format!("{h}\n{m}: /subscribersoff\nSubscribers-only mode is now disabled", h = markup.header("🔒")),
+        kind @ ("add_blocked_term" | "add_permitted_term" | "remove_blocked_term" | "remove_permitted_term") => {
+            // e.g `term_add_block`, or `temp_term_add_block` for terms added by automod
+            let action = match kind {
+                "add_blocked_term" => "term_add_block",
+                "add_permitted_term" => "term_add_permit",
+                "remove_blocked_term" => "term_remove_block",
+                _ => "term_remove_permit",
+            };
+            let action = if event.temporary {
+                format!("temp_{action}")
+            } else {
+                action.to_owned()
+            };
+            format!(
+                "{h}\n{m}: /{action} {terms}\nTerms {action}ed{temp}: {terms}",
+                h = markup.header("🔨"),
+                temp = if event.temporary { " temporarily" } else { "" },
+                terms = event.terms.join(", "),
+            )
+        }
+        "warn" => format!(
+            "{h}\n{m}: /warn {usercard}\n{user} has been warned{chat_rules_cited}{reason}",
+            h = markup.header("🔨"),
+            chat_rules_cited = if event.rules.is_empty() {
+                "".to_string()
+            } else {
+                format!(" for breaking rules: {}", event.rules.join(", "))
+            },
+        ),
+        _ => {
+            tracing::warn!("Unknown action {:?}", event);
+            return None;
+        }
+    };
+    if event.detected_on_reconnect {
+        let detected = markup.emphasis("detected on reconnect");
+        match event.kind.as_str() {
+            "ban" => message.push_str(&format!(
+                "\n{detected}, banned {}",
+                markup.time(event.ban.as_ref().map_or("", |b| b.created_at.as_str()))
+            )),
+            _ => message.push_str(&format!(
+                "\n{detected}, twitch doesn't say who lifted the ban or when"
+            )),
+        }
+    }
+    if event.stream == crate::stream::OFFLINE && crate::stream::is_action(event) {
+        message.push_str(&format!(
+            "\n{}",
+            markup.emphasis("🌙 done while the stream was offline")
+        ));
+    }
+    if let Some(ref info) = event.target_info {
+        message.push_str(&format!(
+            "\naccount created {created}{broadcaster_type}{profile_image}",
+            created = markup.time_ago(&info.created_at),
+            broadcaster_type = if info.broadcaster_type.is_empty() {
+                "".to_owned()
+            } else {
+                format!(", {}", info.broadcaster_type)
+            },
+            profile_image = match info.profile_image_url {
+                Some(ref url) => format!(", {}", markup.link("profile image", url)),
+                None => "".to_owned(),
+            },
+        ));
+    }
+    match markup {
+        Markup::Html => Some(message.replace('\n', "<br>")),
+        _ => Some(message),
+    }
+}
+
+/// How long it was from `start` to `end`, both in RFC 3339, e.g `1h 12m 3s`.
+pub fn elapsed(start: &str, end: &str) -> String {
+    match (
+        types::Timestamp::new(start.to_owned()),
+        types::Timestamp::new(end.to_owned()),
+    ) {
+        (Ok(start), Ok(end)) => {
+            let elapsed =
+                Duration::try_from(end.to_utc() - start.to_utc()).unwrap_or(Duration::ZERO);
+            humantime::format_duration(Duration::from_secs(elapsed.as_secs())).to_string()
+        }
+        _ => "an unknown time".to_owned(),
+    }
+}
+
+/// `count` and a noun in singular or plural, e.g `1 ban` or `2 bans`.
+fn plural(count: u64, singular: &str, plural: &str) -> String {
+    match count {
+        1 => format!("{count} {singular}"),
+        _ => format!("{count} {plural}"),
+    }
+}
+
+/// Moderators and users to list in a stream summary at most
+const MAX_TOP: usize = 5;
+
+/// The summary of a stream that ended at `ended_at`.
+fn stream_summary(markup: Markup, summary: &StreamSummary, ended_at: &str) -> String {
+    let top = |counts: &BTreeMap<String, u64>| {
+        let mut counts = counts.iter().collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(a.1));
+        counts
+            .iter()
+            .take(MAX_TOP)
+            .map(|(login, count)| format!("{} ({count})", markup.emphasis(&markup.escape(login))))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut actions = summary.actions.iter().collect::<Vec<_>>();
+    actions.sort_by(|a, b| b.1.cmp(a.1));
+    let mut message = format!(
+        "the stream lasted {elapsed}, {total}",
+        elapsed = elapsed(&summary.started_at, ended_at),
+        total = plural(summary.actions.values().sum(), "action", "actions"),
+    );
+    if !actions.is_empty() {
+        message.push_str(&format!(
+            ": {}",
+            actions
+                .iter()
+                .map(|(kind, count)| format!("{count} {kind}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if !summary.moderators.is_empty() {
+        message.push_str(&format!(
+            "\nmost active moderators: {}",
+            top(&summary.moderators)
+        ));
+    }
+    if !summary.targets.is_empty() {
+        message.push_str(&format!("\nmost actioned users: {}", top(&summary.targets)));
+    }
+    if summary.automod_terms_added > 0 || summary.automod_terms_removed > 0 {
+        message.push_str(&format!(
+            "\nautomod added {} and removed {} terms",
+            summary.automod_terms_added, summary.automod_terms_removed
+        ));
+    }
+    message
+}
+
+/// Moderators to list in a Shield Mode summary at most
+const MAX_SHIELD_MODERATORS: usize = 10;
+
+/// The summary of Shield Mode that was turned off at `ended_at`.
+fn shield_summary(markup: Markup, summary: &ShieldSummary, ended_at: &str) -> String {
+    let mut message = format!(
+        "Shield Mode was on for {duration}: {}, {}, {}",
+        plural(summary.bans, "ban", "bans"),
+        plural(summary.timeouts, "timeout", "timeouts"),
+        plural(summary.deletes, "delete", "deletes"),
+        duration = elapsed(&summary.started_at, ended_at),
+    );
+    if let (Some(first), Some(last)) = (&summary.first_target, &summary.last_target) {
+        message.push_str(&format!(
+            "\n{} banned, first offender {}, last offender {}",
+            plural(summary.banned.len() as u64, "user", "users"),
+            markup.escape(first),
+            markup.escape(last)
+        ));
+    }
+    if !summary.moderators.is_empty() {
+        let mut moderators = summary.moderators.iter().collect::<Vec<_>>();
+        moderators.sort_by(|a, b| b.1.cmp(a.1));
+        let mut list = moderators
+            .iter()
+            .take(MAX_SHIELD_MODERATORS)
+            .map(|(login, count)| format!("{} ({count})", markup.emphasis(&markup.escape(login))))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if moderators.len() > MAX_SHIELD_MODERATORS {
+            list.push_str(&format!(
+                " and {} more",
+                moderators.len() - MAX_SHIELD_MODERATORS
+            ));
+        }
+        message.push_str(&format!("\nby moderator: {list}"));
+    }
+    message
+}
+
+/// Members to list in a `roster` post at most
+const MAX_MEMBERS: usize = 50;
+
+/// A list of moderators or VIPs, with when they were added if the bot saw it.
+fn members(markup: Markup, members: &[crate::event::Member]) -> String {
+    if members.is_empty() {
+        return "none".to_owned();
+    }
+    let mut list = members
+        .iter()
+        .take(MAX_MEMBERS)
+        .map(|member| {
+            let login = markup.escape(&member.user.login);
+            match member.added_at {
+                Some(ref added_at) => format!("{login} (added {})", markup.time_ago(added_at)),
+                None => login.into_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    if members.len() > MAX_MEMBERS {
+        list.push_str(&format!(" and {} more", members.len() - MAX_MEMBERS));
+    }
+    list
+}
+
+/// Render the recent chat messages of the target of an action, or `None` if there are none.
+pub fn chat_context(event: &ModerationEvent, markup: Markup) -> Option<String> {
+    if event.chat_context.is_empty() {
+        return None;
+    }
+    let mut context = format!(
+        "recent messages of {}:",
+        markup.escape(&event.target.as_ref()?.login)
+    );
+    for message in &event.chat_context {
+        context.push_str(&format!(
+            "\n{} {}",
+            markup.time(&message.timestamp),
+            markup.spoiler(&markup.escape(&message.text))
+        ));
+    }
+    match markup {
+        Markup::Html => Some(context.replace('\n', "<br>")),
+        _ => Some(context),
+    }
+}
+
+/// Render an action with the recent chat messages of its target.
+pub fn with_chat_context(event: &ModerationEvent, markup: Markup) -> Option<String> {
+    let message = moderator_action(event, markup)?;
+    let newline = match markup {
+        Markup::Html => "<br>",
+        _ => "\n",
+    };
+    Some(match chat_context(event, markup) {
+        Some(context) => format!("{message}{newline}{context}"),
+        None => message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    #[test]
+    fn timeout() {
+        let timestamp = types::Timestamp::new("2024-01-01T00:00:00Z".to_owned()).unwrap();
+        let mut event = ModerationEvent::new(
+            "timeout",
+            &timestamp,
+            user("12826", "twitch"),
+            Some(user("1", "justintv")),
+        );
+        event.target = Some(user("2", "some_one"));
+        event.reason = Some("said *hi*".to_owned());
+        event.duration_seconds = Some(600);
+        event.expires_at = Some("2024-01-01T00:10:00Z".to_owned());
+        assert_eq!(
+            moderator_action(&event, Markup::Discord).unwrap(),
+            "🔨_Twitch Moderation_ |\n*justintv*: /timeout [some_one](<https://www.twitch.tv/popout/twitch/viewercard/some_one?popout=>)\n*[some_one](<https://www.twitch.tv/popout/twitch/viewercard/some_one?popout=>):2* has been timed out until <t:1704067800>\nreason: said \\*hi\\*"
+        );
+        assert_eq!(
+            moderator_action(&event, Markup::Plain).unwrap(),
+            "🔨Twitch Moderation |\njustintv: /timeout some_one\nsome_one:2 has been timed out until 2024-01-01T00:10:00Z\nreason: said *hi*"
+        );
+
+        event.target_info = Some(crate::event::UserInfo {
+            created_at: "2024-01-01T00:10:00Z".to_owned(),
+            profile_image_url: Some("https://example.com/a.png".to_owned()),
+            broadcaster_type: "".to_owned(),
+        });
+        assert!(moderator_action(&event, Markup::Discord)
+            .unwrap()
+            .ends_with(
+                "\naccount created <t:1704067800:R>, [profile image](<https://example.com/a.png>)"
+            ));
+
+        event.chat_context = vec![crate::event::ChatMessage {
+            timestamp: "2024-01-01T00:10:00Z".to_owned(),
+            message_id: "3".to_owned(),
+            text: "say *hi*".to_owned(),
+        }];
+        assert_eq!(
+            chat_context(&event, Markup::Plain).unwrap(),
+            "recent messages of some_one:\n2024-01-01T00:10:00Z say *hi*"
+        );
+
+        // chatters can't end the spoiler early
+        event.chat_context[0].text = "|| @everyone".to_owned();
+        assert_eq!(
+            chat_context(&event, Markup::Discord).unwrap(),
+            "recent messages of some\\_one:\n<t:1704067800> ||\\|\\| @everyone||"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Reporting errors to a discord webhook for the people running the bot.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::supervisor::RestartState;
+
+/// Reports can't be longer than this, leaving room for mentioning `--ops-mention-role` in a discord message
+const MAX_CONTENT_LEN: usize = 1900;
+
+/// How serious a report is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The bot will restart and try again.
+    Warning,
+    /// The bot can not recover by itself.
+    Error,
+}
+
+/// Posts warnings and errors to a webhook, deduplicating identical errors and rate-limiting posts.
+pub struct Reporter {
+    url: crate::Secret,
+    http: serenity::http::Http,
+    mention_role: Option<serenity::all::RoleId>,
+    /// Minimum time between two posts.
+    min_interval: Duration,
+    /// Minimum time before posting the same error again.
+    repeat_interval: Duration,
+    state: Mutex<ReporterState>,
+}
+
+#[derive(Default)]
+struct ReporterState {
+    webhook: Option<serenity::model::webhook::Webhook>,
+    last_post: Option<Instant>,
+    /// Errors seen since the last recovery, by hash of their chain.
+    seen: HashMap<u64, Seen>,
+    /// Reports that were not posted because of rate-limiting.
+    suppressed: u32,
+    /// When the bot started failing, if an error has been reported without a recovery.
+    failing_since: Option<Instant>,
+}
+
+impl ReporterState {
+    /// Remember that the error with `key` was posted.
+    fn reported(&mut self, key: u64) {
+        self.suppressed = 0;
+        if let Some(seen) = self.seen.get_mut(&key) {
+            seen.last_posted = Some(Instant::now());
+        }
+    }
+
+    /// Forget the errors, after posting that the bot recovered.
+    fn recovered(&mut self) {
+        self.failing_since = None;
+        self.seen.clear();
+        self.suppressed = 0;
+    }
+}
+
+struct Seen {
+    count: u32,
+    last_posted: Option<Instant>,
+}
+
+impl Reporter {
+    /// Create a reporter if `--ops-webhook` or `--discord-error-report` is set.
+    pub fn from_opts(client: &reqwest::Client, opts: &crate::Opts) -> Option<Self> {
+        let url = match (&opts.ops_webhook, opts.discord_error_report) {
+            (Some(url), _) => url.clone(),
+            (None, true) => opts.discord_webhook().clone(),
+            (None, false) => return None,
+        };
+        Some(Self {
+            url,
+            http: serenity::http::HttpBuilder::without_token()
+                .client(client.clone())
+                .build(),
+            mention_role: opts
+                .ops_mention_role
+                .map(|id| serenity::all::RoleId::new(id.get())),
+            min_interval: opts.ops_min_interval,
+            repeat_interval: opts.ops_repeat_interval,
+            state: Mutex::new(ReporterState::default()),
+        })
+    }
+
+    /// Report an error. Identical errors are only posted once per `--ops-repeat-interval`.
+    pub async fn error(&self, level: Level, err: &eyre::Report, restart: &RestartState) {
+        let chain = crate::supervisor::format_chain(err);
+        let mut state = self.state.lock().await;
+        let Some((key, content)) = self.error_report(&mut state, level, &chain, restart) else {
+            return;
+        };
+        if self.post(&mut state, level == Level::Error, content).await {
+            state.reported(key);
+        }
+    }
+
+    /// Count an error with `chain`, returning its key and what to post if it should be posted.
+    fn error_report(
+        &self,
+        state: &mut ReporterState,
+        level: Level,
+        chain: &str,
+        restart: &RestartState,
+    ) -> Option<(u64, String)> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        chain.hash(&mut hasher);
+        let key = hasher.finish();
+
+        state.failing_since.get_or_insert_with(Instant::now);
+        let seen = state.seen.entry(key).or_insert(Seen {
+            count: 0,
+            last_posted: None,
+        });
+        seen.count += 1;
+        let count = seen.count;
+        if seen
+            .last_posted
+            .is_some_and(|at| at.elapsed() < self.repeat_interval)
+        {
+            tracing::debug!("not reporting error, it was reported recently");
+            return None;
+        }
+        if state
+            .last_post
+            .is_some_and(|at| at.elapsed() < self.min_interval)
+        {
+            tracing::debug!("not reporting error, rate-limited");
+            state.suppressed += 1;
+            return None;
+        }
+
+        let (emoji, what) = match level {
+            Level::Warning => ("⚠️", "The bot ran into an error and will restart"),
+            Level::Error => (
+                "🛑",
+                "The bot can not recover from an error by itself, it will keep retrying",
+            ),
+        };
+        let mut content = format!(
+            "{emoji} {what}. Errors in a row: {attempt}, restarts: {restarts}",
+            attempt = restart.attempt,
+            restarts = restart.restarts,
+        );
+        if let Some(class) = restart.last_class {
+            content.push_str(&format!(", kind: {class}"));
+        }
+        if count > 1 {
+            content.push_str(&format!("\nThis error has happened {count} times"));
+        }
+        if state.suppressed > 0 {
+            content.push_str(&format!(
+                "\n{} other reports were suppressed",
+                state.suppressed
+            ));
+        }
+        // the chain can be long, e.g with a response body in it
+        let max = MAX_CONTENT_LEN.saturating_sub(content.len() + "\n```\n…```".len());
+        let truncated = crate::util::truncate(chain, max);
+        let ellipsis = if truncated.len() < chain.len() {
+            "…"
+        } else {
+            ""
+        };
+        content.push_str(&format!("\n```\n{truncated}{ellipsis}```"));
+        Some((key, content))
+    }
+
+    /// Report that the bot works again, if an error was reported before.
+    pub async fn recovered(&self, restart: &RestartState) {
+        let mut state = self.state.lock().await;
+        let Some(content) = recovery_report(&state, restart) else {
+            return;
+        };
+        // always post recoveries, they are rare and important
+        if self.post(&mut state, false, content).await {
+            state.recovered();
+        }
+    }
+
+    async fn post(&self, state: &mut ReporterState, mention: bool, content: String) -> bool {
+        let result = self.execute(state, mention, content).await;
+        state.last_post = Some(Instant::now());
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(error = %e, "could not send report to discord");
+                false
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        state: &mut ReporterState,
+        mention: bool,
+        mut content: String,
+    ) -> Result<(), serenity::Error> {
+        let webhook = match state.webhook {
+            Some(ref webhook) => webhook,
+            None => state.webhook.insert(
+                serenity::model::webhook::Webhook::from_url(&self.http, self.url.secret()).await?,
+            ),
+        };
+        let mut message = serenity::all::ExecuteWebhook::new()
+            .username("twitch_moderation")
+            .allowed_mentions(serenity::all::CreateAllowedMentions::new());
+        if let (true, Some(role)) = (mention, self.mention_role) {
+            content = format!("<@&{}> {content}", role.get());
+            message = message
+                .allowed_mentions(serenity::all::CreateAllowedMentions::new().roles(vec![role]));
+        }
+        webhook
+            .execute(&self.http, false, message.content(content))
+            .await?;
+        Ok(())
+    }
+}
+
+/// What to post when the bot works again, if an error was reported before.
+fn recovery_report(state: &ReporterState, restart: &RestartState) -> Option<String> {
+    let since = state.failing_since?;
+    let errors: u32 = state.seen.values().map(|s| s.count).sum();
+    Some(format!(
+        "✅ The bot has recovered after {}. Errors: {errors}, restarts: {restarts}",
+        humantime::format_duration(Duration::from_secs(since.elapsed().as_secs())),
+        restarts = restart.restarts,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reporter(min_interval: Duration, repeat_interval: Duration) -> Reporter {
+        Reporter {
+            url: "https://discord.com/api/webhooks/1/a".parse().unwrap(),
+            http: serenity::http::HttpBuilder::without_token().build(),
+            mention_role: None,
+            min_interval,
+            repeat_interval,
+            state: Mutex::new(ReporterState::default()),
+        }
+    }
+
+    #[test]
+    fn deduplicates_errors() {
+        let reporter = reporter(Duration::ZERO, Duration::from_secs(3600));
+        let restart = RestartState::default();
+        let mut state = ReporterState::default();
+
+        let (key, content) = reporter
+            .error_report(&mut state, Level::Warning, "> connection reset\n", &restart)
+            .unwrap();
+        assert!(content.ends_with("```\n> connection reset\n```"));
+        state.reported(key);
+        // reported recently
+        assert!(reporter
+            .error_report(&mut state, Level::Warning, "> connection reset\n", &restart)
+            .is_none());
+        assert!(reporter
+            .error_report(&mut state, Level::Warning, "> bad gateway\n", &restart)
+            .is_some());
+
+        // the repeat interval passed
+        let reporter = self::reporter(Duration::ZERO, Duration::ZERO);
+        let (_, content) = reporter
+            .error_report(&mut state, Level::Warning, "> connection reset\n", &restart)
+            .unwrap();
+        assert!(content.contains("\nThis error has happened 3 times\n"));
+    }
+
+    #[test]
+    fn rate_limits_reports() {
+        let reporter = reporter(Duration::from_secs(3600), Duration::ZERO);
+        let restart = RestartState::default();
+        let mut state = ReporterState::default();
+
+        let (key, _) = reporter
+            .error_report(&mut state, Level::Warning, "> connection reset\n", &restart)
+            .unwrap();
+        state.last_post = Some(Instant::now());
+        state.reported(key);
+        assert!(reporter
+            .error_report(&mut state, Level::Warning, "> bad gateway\n", &restart)
+            .is_none());
+
+        // the min interval passed
+        let reporter = self::reporter(Duration::ZERO, Duration::ZERO);
+        let (_, content) = reporter
+            .error_report(&mut state, Level::Error, "> unauthorized\n", &restart)
+            .unwrap();
+        assert!(content.contains("\n1 other reports were suppressed\n"));
+    }
+
+    #[test]
+    fn reports_recovery_after_errors() {
+        let reporter = reporter(Duration::ZERO, Duration::ZERO);
+        let restart = RestartState {
+            restarts: 2,
+            ..RestartState::default()
+        };
+        let mut state = ReporterState::default();
+        assert!(recovery_report(&state, &restart).is_none());
+
+        for _ in 0..2 {
+            reporter.error_report(&mut state, Level::Warning, "> connection reset\n", &restart);
+        }
+        let content = recovery_report(&state, &restart).unwrap();
+        assert!(content.ends_with("Errors: 2, restarts: 2"), "{content}");
+        state.recovered();
+        assert!(recovery_report(&state, &restart).is_none());
+    }
+
+    #[test]
+    fn truncates_long_errors() {
+        let reporter = reporter(Duration::ZERO, Duration::ZERO);
+        let chain = "> response body: ä\n".repeat(200);
+        let (_, content) = reporter
+            .error_report(
+                &mut ReporterState::default(),
+                Level::Error,
+                &chain,
+                &RestartState::default(),
+            )
+            .unwrap();
+        assert!(content.len() <= MAX_CONTENT_LEN);
+        assert!(content.ends_with("…```"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Keeping track of the moderators and VIPs of channels.
+
+use std::collections::{BTreeMap, HashMap};
+
+use eyre::WrapErr;
+use tokio::sync::Mutex;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::{helix, types, HelixClient};
+
+use crate::event::{Member, ModerationEvent, User};
+
+/// The moderators and VIPs of `channel`, from Helix `get_moderators` and `get_vips`.
+pub async fn get_roster(
+    client: &HelixClient<'static, reqwest::Client>,
+    token: &Mutex<UserToken>,
+    channel: &types::UserIdRef,
+) -> Result<(Vec<User>, Vec<User>), eyre::Report> {
+    let token = token.lock().await;
+    let mut moderators = vec![];
+    let mut response = Some(
+        client
+            .req_get(
+                helix::moderation::GetModeratorsRequest::broadcaster_id(channel),
+                &*token,
+            )
+            .await
+            .wrap_err("could not get moderators")?,
+    );
+    while let Some(page) = response {
+        moderators.extend(
+            page.data
+                .iter()
+                .map(|m| User::new(&m.user_id, &m.user_login, &m.user_name)),
+        );
+        response = page
+            .get_next(client, &*token)
+            .await
+            .wrap_err("could not get moderators")?;
+    }
+    let mut vips = vec![];
+    let mut response = Some(
+        client
+            .req_get(
+                helix::channels::GetVipsRequest::broadcaster_id(channel),
+                &*token,
+            )
+            .await
+            .wrap_err("could not get vips")?,
+    );
+    while let Some(page) = response {
+        vips.extend(
+            page.data
+                .iter()
+                .map(|v| User::new(&v.user_id, &v.user_login, &v.user_name)),
+        );
+        response = page
+            .get_next(client, &*token)
+            .await
+            .wrap_err("could not get vips")?;
+    }
+    Ok((moderators, vips))
+}
+
+/// Moderators and VIPs of a channel, by user id.
+#[derive(Debug, Default)]
+struct ChannelRoster {
+    moderators: BTreeMap<String, Member>,
+    vips: BTreeMap<String, Member>,
+}
+
+/// The moderators and VIPs of channels, by channel id.
+#[derive(Default)]
+pub struct Roster(std::sync::Mutex<HashMap<String, ChannelRoster>>);
+
+impl Roster {
+    pub fn insert(&self, channel_id: &str, moderators: Vec<User>, vips: Vec<User>) {
+        let members = |users: Vec<User>| {
+            users
+                .into_iter()
+                .map(|user| {
+                    (
+                        user.id.clone(),
+                        Member {
+                            user,
+                            added_at: None,
+                        },
+                    )
+                })
+                .collect()
+        };
+        self.0.lock().unwrap().insert(
+            channel_id.to_owned(),
+            ChannelRoster {
+                moderators: members(moderators),
+                vips: members(vips),
+            },
+        );
+    }
+
+    pub fn remove(&self, channel_id: &str) {
+        self.0.lock().unwrap().remove(channel_id);
+    }
+
+    /// Keep track of `mod`, `unmod`, `vip` and `unvip` actions and `moderator_add`, `moderator_remove`, `vip_add` and `vip_remove` events.
+    ///
+    /// Returns `false` for changes that were already seen,
+    /// e.g when a moderator used `/mod` and twitch sends both the action and `moderator_add`.
+    pub fn observe(&self, event: &ModerationEvent) -> bool {
+        let Some(ref target) = event.target else {
+            return true;
+        };
+        let mut roster = self.0.lock().unwrap();
+        let Some(channel) = roster.get_mut(&event.channel.id) else {
+            return true;
+        };
+        let (members, add) = match event.kind.as_str() {
+            "mod" | "moderator_add" => (&mut channel.moderators, true),
+            "unmod" | "moderator_remove" => (&mut channel.moderators, false),
+            "vip" | "vip_add" => (&mut channel.vips, true),
+            "unvip" | "vip_remove" => (&mut channel.vips, false),
+            _ => return true,
+        };
+        if add {
+            let member = Member {
+                user: target.clone(),
+                added_at: Some(event.timestamp.clone()),
+            };
+            members.insert(target.id.clone(), member).is_none()
+        } else {
+            members.remove(&target.id).is_some()
+        }
+    }
+
+    /// A `roster` event listing the moderators and VIPs of `channel`, `None` if they couldn't be fetched at startup.
+    pub fn event(&self, channel: User) -> Option<ModerationEvent> {
+        let roster = self.0.lock().unwrap();
+        let members = roster.get(&channel.id)?;
+        let mut event = ModerationEvent::new("roster", &types::Timestamp::now(), channel, None);
+        event.moderators = members.moderators.values().cloned().collect();
+        event.vips = members.vips.values().cloned().collect();
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    #[test]
+    fn posts_changes_once() {
+        let timestamp = types::Timestamp::new("2024-01-01T00:00:00Z".to_owned()).unwrap();
+        let roster = Roster::default();
+        roster.insert("1", vec![user("2", "some_mod")], vec![]);
+
+        let mut action = ModerationEvent::new(
+            "vip",
+            &timestamp,
+            user("1", "twitch"),
+            Some(user("2", "some_mod")),
+        );
+        action.target = Some(user("3", "justintv"));
+        assert!(roster.observe(&action));
+
+        // the event twitch sends for the `/vip` above
+        let mut add = action.clone();
+        add.kind = "vip_add".to_owned();
+        assert!(!roster.observe(&add));
+
+        // removed on the dashboard
+        let mut remove =
+            ModerationEvent::new("moderator_remove", &timestamp, user("1", "twitch"), None);
+        remove.target = Some(user("2", "some_mod"));
+        assert!(roster.observe(&remove));
+
+        let event = roster.event(user("1", "twitch")).unwrap();
+        assert!(event.moderators.is_empty());
+        assert_eq!(
+            event.vips,
+            [Member {
+                user: user("3", "justintv"),
+                added_at: Some("2024-01-01T00:00:00Z".to_owned()),
+            }]
+        );
+    }
+}
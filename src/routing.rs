@@ -0,0 +1,168 @@
+//! Routing events to different webhooks, see `routes` in the config file.
+
+use std::num::NonZeroU64;
+
+/// A webhook to post to, optionally in a thread of the channel.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Destination {
+    #[serde(deserialize_with = "deserialize_url")]
+    pub webhook: crate::Secret,
+    pub thread_id: Option<NonZeroU64>,
+}
+
+pub(crate) fn deserialize_url<'de, D: serde::Deserializer<'de>>(
+    d: D,
+) -> Result<crate::Secret, D::Error> {
+    let url = <String as serde::Deserialize>::deserialize(d)?;
+    crate::is_url(&url).map_err(serde::de::Error::custom)
+}
+
+/// Sends events matching all of the given conditions to some destinations. Empty conditions match everything.
+///
+/// ```toml
+/// [[routes]]
+/// actions = ["ban", "unban", "timeout", "untimeout"]
+/// to = [{ webhook = "https://discord.com/api/webhooks/111111111111/aaaaaaaaaaaaaaa" }]
+///
+/// [[routes]]
+/// actions = ["approve_unban_request", "deny_unban_request"]
+/// to = [{ webhook = "https://discord.com/api/webhooks/222222222222/bbbbbbbbbbbbbbb", thread-id = 333333333333 }]
+/// ```
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Route {
+    /// Kinds of actions, e.g `ban` or `slowoff`
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// Logins of channels the action happened in
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Logins of moderators that did the action
+    #[serde(default)]
+    pub moderators: Vec<String>,
+    /// Logins of users the action was done on
+    #[serde(default)]
+    pub targets: Vec<String>,
+    pub to: Vec<Destination>,
+}
+
+/// What an event is about, for matching against routes.
+#[derive(Debug, Clone, Copy)]
+pub struct Subject<'a> {
+    pub kind: &'a str,
+    pub channel: &'a str,
+    pub moderator: Option<&'a str>,
+    pub target: Option<&'a str>,
+    pub reason: Option<&'a str>,
+    pub message: Option<&'a str>,
+}
+
+fn contains(list: &[String], value: &str) -> bool {
+    list.is_empty() || list.iter().any(|v| v.eq_ignore_ascii_case(value))
+}
+
+impl Subject<'_> {
+    /// Whether the action is one of `actions` and the logins are in the lists, ignoring case. Empty lists match
+    /// everything, a non-empty list of moderators or targets doesn't match an event without one.
+    pub fn matches(
+        &self,
+        actions: &[String],
+        channels: &[String],
+        moderators: &[String],
+        targets: &[String],
+    ) -> bool {
+        contains(actions, self.kind)
+            && contains(channels, self.channel)
+            && (moderators.is_empty() || self.moderator.is_some_and(|m| contains(moderators, m)))
+            && (targets.is_empty() || self.target.is_some_and(|t| contains(targets, t)))
+    }
+}
+
+impl Route {
+    pub fn matches(&self, subject: &Subject<'_>) -> bool {
+        subject.matches(
+            &self.actions,
+            &self.channels,
+            &self.moderators,
+            &self.targets,
+        )
+    }
+}
+
+/// Destinations for an event, without duplicates. Falls back to `default` if no route matches.
+pub fn destinations<'a>(
+    routes: &'a [Route],
+    default: &'a Destination,
+    subject: &Subject<'_>,
+) -> Vec<&'a Destination> {
+    let mut destinations: Vec<&Destination> = vec![];
+    for route in routes.iter().filter(|r| r.matches(subject)) {
+        for destination in &route.to {
+            if !destinations.contains(&destination) {
+                destinations.push(destination);
+            }
+        }
+    }
+    if destinations.is_empty() {
+        destinations.push(default);
+    }
+    destinations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn destination(url: &str) -> Destination {
+        Destination {
+            webhook: url.parse().unwrap(),
+            thread_id: None,
+        }
+    }
+
+    #[test]
+    fn fans_out_and_falls_back() {
+        let routes = toml::from_str::<crate::config::Config>(
+            r#"
+            [[routes]]
+            actions = ["ban", "timeout"]
+            to = [{ webhook = "https://bans" }, { webhook = "https://all" }]
+
+            [[routes]]
+            moderators = ["Justintv"]
+            to = [{ webhook = "https://all" }, { webhook = "https://thread", thread-id = 1 }]
+            "#,
+        )
+        .unwrap()
+        .routes;
+        let default = destination("https://default");
+        let subject = |kind, moderator| Subject {
+            kind,
+            channel: "twitch",
+            moderator: Some(moderator),
+            target: Some("someone"),
+            reason: None,
+            message: None,
+        };
+        let urls = |subject: Subject<'_>| {
+            destinations(&routes, &default, &subject)
+                .into_iter()
+                .map(|d| d.webhook.secret().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(urls(subject("ban", "mod")), ["https://bans", "https://all"]);
+        assert_eq!(
+            urls(subject("ban", "justintv")),
+            ["https://bans", "https://all", "https://thread"]
+        );
+        assert_eq!(urls(subject("slow", "mod")), ["https://default"]);
+        // twitch didn't say who did it
+        let unknown = Subject {
+            moderator: None,
+            ..subject("slow", "justintv")
+        };
+        assert_eq!(urls(unknown), ["https://default"]);
+    }
+}
@@ -0,0 +1,61 @@
+//! HTTP server for health checks and metrics.
+
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use eyre::WrapErr;
+
+use crate::health::Health;
+
+pub fn router(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .route("/metrics", get(metrics))
+        .with_state(health)
+}
+
+/// Serve the health endpoints on an already bound listener.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    health: Arc<Health>,
+) -> Result<(), eyre::Report> {
+    crate::metrics::init();
+    tracing::info!(
+        "serving health endpoints on http://{}",
+        listener.local_addr()?
+    );
+    axum::serve(listener, router(health))
+        .await
+        .wrap_err("http server failed")
+}
+
+/// Liveness, the process is up and answering.
+async fn healthz(State(health): State<Arc<Health>>) -> Json<serde_json::Value> {
+    Json(health.report())
+}
+
+/// Readiness, the bot is connected to twitch and can post to discord.
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<serde_json::Value>) {
+    let status = if health.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(health.report()))
+}
+
+async fn version() -> String {
+    crate::util::LONG_VERSION.clone()
+}
+
+async fn metrics() -> (StatusCode, String) {
+    match crate::metrics::gather() {
+        Ok(metrics) => (StatusCode::OK, metrics),
+        Err(e) => {
+            tracing::error!(error = ?e, "could not gather metrics");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Keeping track of the chat settings of channels, to post what changed.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::event::{ChatSettings, ModerationEvent};
+
+/// Chat settings from Helix `get_chat_settings`, without the non-moderator chat delay, see [`ChatSettings`].
+pub fn from_helix(settings: &twitch_api::helix::chat::ChatSettings) -> ChatSettings {
+    ChatSettings {
+        emote_mode: settings.emote_mode,
+        follower_mode_minutes: settings
+            .follower_mode
+            .then(|| settings.follower_mode_duration.unwrap_or(0)),
+        slow_mode_seconds: settings
+            .slow_mode
+            .then(|| settings.slow_mode_wait_time.unwrap_or(0)),
+        subscriber_mode: settings.subscriber_mode,
+        unique_chat_mode: settings.unique_chat_mode,
+    }
+}
+
+/// The chat settings of a channel as shown to moderators, e.g `("slow mode", "30s")`.
+pub fn describe(settings: &ChatSettings) -> [(&'static str, String); 5] {
+    let on_off = |on: bool| if on { "on" } else { "off" }.to_owned();
+    let duration = |duration: Option<u64>| match duration {
+        Some(0) => "on".to_owned(),
+        Some(seconds) => humantime::format_duration(Duration::from_secs(seconds)).to_string(),
+        None => "off".to_owned(),
+    };
+    [
+        ("emote-only", on_off(settings.emote_mode)),
+        (
+            "followers-only",
+            duration(settings.follower_mode_minutes.map(|m| m * 60)),
+        ),
+        ("slow mode", duration(settings.slow_mode_seconds)),
+        ("subscribers-only", on_off(settings.subscriber_mode)),
+        ("unique chat", on_off(settings.unique_chat_mode)),
+    ]
+}
+
+/// What changed between two settings, e.g `slow mode: off → 30s`.
+pub fn changes(previous: &ChatSettings, settings: &ChatSettings) -> Vec<String> {
+    describe(previous)
+        .into_iter()
+        .zip(describe(settings))
+        .filter(|((_, before), (_, after))| before != after)
+        .map(|((name, before), (_, after))| format!("{name}: {before} → {after}"))
+        .collect()
+}
+
+/// The last known chat settings of channels, by channel id.
+#[derive(Default)]
+pub struct KnownSettings(Mutex<HashMap<String, ChatSettings>>);
+
+impl KnownSettings {
+    pub fn insert(&self, channel_id: &str, settings: ChatSettings) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(channel_id.to_owned(), settings);
+    }
+
+    pub fn remove(&self, channel_id: &str) {
+        self.0.lock().unwrap().remove(channel_id);
+    }
+
+    /// Keep track of chat mode actions and `chat_settings_update` events.
+    ///
+    /// Sets [`ModerationEvent::previous_chat_settings`] and returns `false` for updates that change nothing,
+    /// e.g when a moderator used `/slow` and twitch sends both the action and the update.
+    pub fn observe(&self, event: &mut ModerationEvent) -> bool {
+        let mut known = self.0.lock().unwrap();
+        if let Some(ref settings) = event.chat_settings {
+            let previous = known.insert(event.channel.id.clone(), settings.clone());
+            if previous.as_ref() == Some(settings) {
+                return false;
+            }
+            event.previous_chat_settings = previous;
+            return true;
+        }
+        let Some(settings) = known.get_mut(&event.channel.id) else {
+            return true;
+        };
+        match event.kind.as_str() {
+            "emoteonly" => settings.emote_mode = true,
+            "emoteonlyoff" => settings.emote_mode = false,
+            "followers" => {
+                settings.follower_mode_minutes = Some(event.duration_seconds.unwrap_or(0) / 60)
+            }
+            "followersoff" => settings.follower_mode_minutes = None,
+            "slow" => settings.slow_mode_seconds = Some(event.duration_seconds.unwrap_or(0)),
+            "slowoff" => settings.slow_mode_seconds = None,
+            "subscribers" => settings.subscriber_mode = true,
+            "subscribersoff" => settings.subscriber_mode = false,
+            "uniquechat" => settings.unique_chat_mode = true,
+            "uniquechatoff" => settings.unique_chat_mode = false,
+            _ => {}
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    #[test]
+    fn posts_only_changes() {
+        let channel = user("1", "twitch");
+        let timestamp =
+            twitch_api::types::Timestamp::new("2024-01-01T00:00:00Z".to_owned()).unwrap();
+        let known = KnownSettings::default();
+        known.insert("1", ChatSettings::default());
+
+        let mut slow =
+            ModerationEvent::new("slow", &timestamp, channel.clone(), Some(channel.clone()));
+        slow.duration_seconds = Some(30);
+        assert!(known.observe(&mut slow));
+
+        // the update twitch sends for the `/slow` above
+        let mut update =
+            ModerationEvent::new("chat_settings_update", &timestamp, channel.clone(), None);
+        update.chat_settings = Some(ChatSettings {
+            slow_mode_seconds: Some(30),
+            ..Default::default()
+        });
+        assert!(!known.observe(&mut update.clone()));
+
+        update.chat_settings = Some(ChatSettings {
+            emote_mode: true,
+            follower_mode_minutes: Some(10),
+            slow_mode_seconds: Some(30),
+            ..Default::default()
+        });
+        assert!(known.observe(&mut update));
+        assert_eq!(
+            changes(
+                update.previous_chat_settings.as_ref().unwrap(),
+                update.chat_settings.as_ref().unwrap()
+            ),
+            ["emote-only: off → on", "followers-only: off → 10m"]
+        );
+    }
+}
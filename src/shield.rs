@@ -0,0 +1,107 @@
+//! Keeping track of Shield Mode, to sum up what moderators did while it was on.
+
+use std::collections::HashMap;
+
+use crate::event::{ModerationEvent, ShieldSummary};
+
+/// Shield Mode in progress in channels, by channel id.
+#[derive(Default)]
+pub struct Shields(std::sync::Mutex<HashMap<String, ShieldSummary>>);
+
+impl Shields {
+    pub fn remove(&self, channel_id: &str) {
+        self.0.lock().unwrap().remove(channel_id);
+    }
+
+    /// Count bans, timeouts and deletes while Shield Mode is on.
+    ///
+    /// Sets [`ModerationEvent::shield_summary`] when Shield Mode is turned off. Shield Mode that was
+    /// already on at startup isn't summed up.
+    pub fn observe(&self, event: &mut ModerationEvent) {
+        let mut shields = self.0.lock().unwrap();
+        match event.kind.as_str() {
+            "shield_mode_begin" => {
+                let summary = ShieldSummary {
+                    started_at: event.timestamp.clone(),
+                    ..ShieldSummary::default()
+                };
+                shields.insert(event.channel.id.clone(), summary);
+            }
+            "shield_mode_end" => event.shield_summary = shields.remove(&event.channel.id),
+            _ => {
+                if let Some(summary) = shields.get_mut(&event.channel.id) {
+                    record(summary, event);
+                }
+            }
+        }
+    }
+}
+
+fn record(summary: &mut ShieldSummary, event: &ModerationEvent) {
+    match event.kind.as_str() {
+        "ban" => summary.bans += 1,
+        "timeout" => summary.timeouts += 1,
+        "delete" => summary.deletes += 1,
+        _ => return,
+    }
+    if let Some(ref moderator) = event.moderator {
+        *summary
+            .moderators
+            .entry(moderator.login.clone())
+            .or_default() += 1;
+    }
+    if let Some(ref target) = event.target {
+        if event.kind == "ban" {
+            summary.banned.insert(target.login.clone());
+        }
+        summary
+            .first_target
+            .get_or_insert_with(|| target.login.clone());
+        summary.last_target = Some(target.login.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+    use crate::render::Markup;
+
+    #[test]
+    fn summary() {
+        let event = |kind: &str, timestamp: &str, moderator: &str, target: &str| {
+            let timestamp = twitch_api::types::Timestamp::new(timestamp.to_owned()).unwrap();
+            let mut event = ModerationEvent::new(
+                kind,
+                &timestamp,
+                user("1", "twitch"),
+                Some(user("1", moderator)),
+            );
+            if !target.is_empty() {
+                event.target = Some(user("1", target));
+            }
+            event
+        };
+        let shields = Shields::default();
+        shields.observe(&mut event(
+            "shield_mode_begin",
+            "2024-01-01T00:00:00Z",
+            "justintv",
+            "",
+        ));
+        for (kind, moderator, target) in [
+            ("ban", "justintv", "spam_bot"),
+            ("emoteonly", "justintv", ""),
+            ("delete", "some_mod", "spam_bot2"),
+            ("ban", "justintv", "spam_bot3"),
+        ] {
+            shields.observe(&mut event(kind, "2024-01-01T00:01:00Z", moderator, target));
+        }
+        let mut end = event("shield_mode_end", "2024-01-01T00:12:03Z", "justintv", "");
+        shields.observe(&mut end);
+        assert_eq!(
+            crate::render::moderator_action(&end, Markup::Discord).unwrap(),
+            "🛡️_Twitch Moderation_ |\n*justintv*: /shieldoff\nShield Mode is now off\nShield Mode was on for 12m 3s: 2 bans, 0 timeouts, 1 delete\n2 users banned, first offender spam\\_bot, last offender spam\\_bot3\nby moderator: *justintv* (2), *some\\_mod* (1)"
+        );
+    }
+}
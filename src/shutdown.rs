@@ -0,0 +1,72 @@
+//! Orderly shutdown on SIGTERM/SIGINT
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Handle to signal and wait for shutdown. Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Tell everything holding this handle to shut down.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Wait until shutdown has been triggered.
+    pub async fn wait(&self) {
+        let mut recv = self.sender.subscribe();
+        // the sender is kept alive by `self`, so this can't fail
+        let _ = recv.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Trigger shutdown when the process receives SIGTERM or SIGINT.
+    pub fn listen_for_signals(&self) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            match signal().await {
+                Ok(signal) => {
+                    tracing::info!("received {signal}, shutting down");
+                    this.trigger();
+                }
+                Err(e) => tracing::error!(error = ?e, "could not listen for signals"),
+            }
+        });
+    }
+}
+
+/// Wait for SIGTERM or SIGINT, returning the name of the signal.
+async fn signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            r = tokio::signal::ctrl_c() => r.map(|_| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.map(|_| "ctrl-c")
+    }
+}
@@ -0,0 +1,217 @@
+//! Keeping track of live streams, to tag events with the stream they happened in and sum up what moderators did.
+
+use std::collections::HashMap;
+
+use eyre::WrapErr;
+use tokio::sync::Mutex;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::{helix, types, HelixClient};
+
+use crate::event::{ModerationEvent, StreamSummary};
+
+/// What [`ModerationEvent::stream`] is set to when the channel isn't live
+pub const OFFLINE: &str = "offline";
+
+/// Kinds that aren't done by moderators, and aren't counted in summaries
+const NOT_ACTIONS: &[&str] = &[
+    "stream_online",
+    "stream_offline",
+    "roster",
+    "unban_request",
+    "suspicious_user_message",
+    "warning_acknowledge",
+    "warning_ignored",
+];
+
+/// The live streams of `channels`, as stream id and when it started by channel id. Channels that aren't live are left out.
+pub async fn get_live(
+    client: &HelixClient<'static, reqwest::Client>,
+    token: &Mutex<UserToken>,
+    channels: &[&types::UserIdRef],
+) -> Result<HashMap<String, (String, String)>, eyre::Report> {
+    let request = helix::streams::GetStreamsRequest::user_ids(channels);
+    let response = {
+        let token = token.lock().await;
+        client
+            .req_get(request, &*token)
+            .await
+            .wrap_err("could not get streams")?
+    };
+    Ok(response
+        .data
+        .into_iter()
+        .map(|stream| {
+            (
+                stream.user_id.as_str().to_owned(),
+                (
+                    stream.id.as_str().to_owned(),
+                    stream.started_at.as_str().to_owned(),
+                ),
+            )
+        })
+        .collect())
+}
+
+/// Whether a moderator did the action, as opposed to e.g a user requesting an unban.
+pub fn is_action(event: &ModerationEvent) -> bool {
+    !NOT_ACTIONS.contains(&event.kind.as_str())
+}
+
+/// A live stream.
+#[derive(Debug)]
+struct Session {
+    id: String,
+    summary: StreamSummary,
+}
+
+/// The live streams of channels by channel id, `None` if the channel is offline.
+#[derive(Default)]
+pub struct Streams(std::sync::Mutex<HashMap<String, Option<Session>>>);
+
+impl Streams {
+    /// Set the stream of a channel, as stream id and when it started, or `None` if it's offline.
+    pub fn insert(&self, channel_id: &str, live: Option<(String, String)>) {
+        let session = live.map(|(id, started_at)| Session {
+            id,
+            summary: StreamSummary {
+                started_at,
+                ..StreamSummary::default()
+            },
+        });
+        self.0
+            .lock()
+            .unwrap()
+            .insert(channel_id.to_owned(), session);
+    }
+
+    pub fn remove(&self, channel_id: &str) {
+        self.0.lock().unwrap().remove(channel_id);
+    }
+
+    /// Set [`ModerationEvent::stream`] and count the action in the summary of the stream.
+    ///
+    /// Sets [`ModerationEvent::stream_summary`] when the stream ends. Events of channels that weren't known
+    /// to be live or offline at startup are only tagged after the next `stream_online` or `stream_offline`.
+    pub fn observe(&self, event: &mut ModerationEvent) {
+        let mut streams = self.0.lock().unwrap();
+        match event.kind.as_str() {
+            "stream_online" => {
+                let session = Session {
+                    id: event.stream.clone(),
+                    summary: StreamSummary {
+                        started_at: event.timestamp.clone(),
+                        ..StreamSummary::default()
+                    },
+                };
+                streams.insert(event.channel.id.clone(), Some(session));
+            }
+            "stream_offline" => match streams.insert(event.channel.id.clone(), None) {
+                Some(Some(session)) => {
+                    event.stream = session.id;
+                    event.stream_summary = Some(session.summary);
+                }
+                _ => event.stream = OFFLINE.to_owned(),
+            },
+            _ => match streams.get_mut(&event.channel.id) {
+                Some(Some(session)) => {
+                    event.stream = session.id.clone();
+                    if is_action(event) {
+                        record(&mut session.summary, event);
+                    }
+                }
+                Some(None) => event.stream = OFFLINE.to_owned(),
+                None => {}
+            },
+        }
+    }
+}
+
+fn record(summary: &mut StreamSummary, event: &ModerationEvent) {
+    *summary.actions.entry(event.kind.clone()).or_default() += 1;
+    if let Some(ref moderator) = event.moderator {
+        *summary
+            .moderators
+            .entry(moderator.login.clone())
+            .or_default() += 1;
+    }
+    if let Some(ref target) = event.target {
+        *summary.targets.entry(target.login.clone()).or_default() += 1;
+    }
+    if event.temporary {
+        let terms = event.terms.len() as u64;
+        match event.kind.as_str() {
+            "add_blocked_term" | "add_permitted_term" => summary.automod_terms_added += terms,
+            "remove_blocked_term" | "remove_permitted_term" => {
+                summary.automod_terms_removed += terms
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    #[test]
+    fn sums_up_streams() {
+        let timestamp = types::Timestamp::new("2024-01-01T00:00:00Z".to_owned()).unwrap();
+        let streams = Streams::default();
+        streams.insert("1", None);
+        let event = |kind: &str, moderator: &str, target: Option<&str>| {
+            let mut event = ModerationEvent::new(
+                kind,
+                &timestamp,
+                user("1", "twitch"),
+                Some(user("2", moderator)),
+            );
+            event.target = target.map(|login| user("3", login));
+            event
+        };
+
+        let mut ban = event("ban", "justintv", Some("spam_bot"));
+        streams.observe(&mut ban);
+        assert_eq!(ban.stream, OFFLINE);
+
+        let mut online = event("stream_online", "twitch", None);
+        online.stream = "42".to_owned();
+        streams.observe(&mut online);
+        for (kind, moderator, target) in [
+            ("ban", "justintv", Some("spam_bot")),
+            ("timeout", "some_mod", Some("spam_bot")),
+            ("unban_request", "spam_bot", Some("spam_bot")),
+            ("emoteonly", "justintv", None),
+        ] {
+            let mut event = event(kind, moderator, target);
+            streams.observe(&mut event);
+            assert_eq!(event.stream, "42");
+        }
+
+        let mut automod = event("add_permitted_term", "justintv", None);
+        automod.terms = vec!["hello".to_owned(), "world".to_owned()];
+        automod.temporary = true;
+        streams.observe(&mut automod);
+
+        let mut offline = event("stream_offline", "twitch", None);
+        streams.observe(&mut offline);
+        assert_eq!(offline.stream, "42");
+        let summary = offline.stream_summary.unwrap();
+        assert_eq!(summary.started_at, "2024-01-01T00:00:00Z");
+        assert_eq!(
+            summary.actions.into_iter().collect::<Vec<_>>(),
+            [
+                ("add_permitted_term".to_owned(), 1),
+                ("ban".to_owned(), 1),
+                ("emoteonly".to_owned(), 1),
+                ("timeout".to_owned(), 1)
+            ]
+        );
+        assert_eq!(summary.moderators["justintv"], 3);
+        assert_eq!(summary.targets["spam_bot"], 2);
+        assert_eq!(
+            (summary.automod_terms_added, summary.automod_terms_removed),
+            (2, 0)
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::event::{ChatMessage, ModerationEvent};
 use eyre::WrapErr;
 use futures::TryStreamExt;
 use std::sync::Arc;
@@ -17,13 +18,24 @@ use twitch_api::{
     HelixClient,
 };
 pub const MOD_NONCE: &str = "moderator";
+
+/// A channel being monitored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub id: twitch_api::types::UserId,
+    pub login: twitch_api::types::UserName,
+}
+
 pub struct Subscriber {
     pub(crate) access_token: twitch_oauth2::UserToken,
     pub channel_id: twitch_api::types::UserId,
     pub channel_login: twitch_api::types::UserName,
+    /// All channels being monitored, including [`Subscriber::channel_id`]
+    pub channels: Vec<Channel>,
     pub token_id: twitch_api::types::UserId,
-    pub channel: sync::broadcast::Sender<Events>,
+    pub channel: crate::sink::Fanout,
     pub client: reqwest::Client,
+    pub health: Arc<crate::health::Health>,
 }
 
 pub async fn make_token(
@@ -154,10 +166,10 @@ pub async fn do_dcf_flow(
         println!("Please visit {} and enter the code: {}", url, code);
         tracing::info!("waiting for user to enter code at {}", url);
         if let Some(msg_id) = sent_message {
-            let message = serenity::all::EditWebhookMessage::new().content(format!("Please visit <{url}> and enter the code: `{code}` to authenticate `twitch_discord_moderation` with twitch!"));
+            let message = serenity::all::EditWebhookMessage::new().content(format!("Please visit <{url}> and enter the code: `{code}` to authenticate `twitch_discord_moderation` with twitch!")).allowed_mentions(serenity::all::CreateAllowedMentions::new());
             webhook.edit_message(discord_http, msg_id, message).await?;
         } else {
-            let message = serenity::all::ExecuteWebhook::new().content(format!("Please visit <{url}> and enter the code: `{code}` to authenticate `twitch_discord_moderation` with twitch!")).username("twitch_moderation");
+            let message = serenity::all::ExecuteWebhook::new().content(format!("Please visit <{url}> and enter the code: `{code}` to authenticate `twitch_discord_moderation` with twitch!")).username("twitch_moderation").allowed_mentions(serenity::all::CreateAllowedMentions::new());
             let Some(resp) = webhook.execute(discord_http, true, message).await? else {
                 eyre::bail!("discord gave no response when it should've for the webhook");
             };
@@ -176,7 +188,8 @@ pub async fn do_dcf_flow(
             discord_http,
             sent_message.unwrap(),
             serenity::all::EditWebhookMessage::new()
-                .content("Successfully authenticated with twitch!"),
+                .content("Successfully authenticated with twitch!")
+                .allowed_mentions(serenity::all::CreateAllowedMentions::new()),
         )
         .await
         .map_err(|e| eyre::eyre!("{e}"))?;
@@ -227,6 +240,14 @@ pub async fn get_access_token(
             Ok(response_error) => {
                 let status = response_error.status();
                 let error = response_error.text().await?;
+                if status == reqwest::StatusCode::UNAUTHORIZED
+                    || status == reqwest::StatusCode::FORBIDDEN
+                {
+                    return Err(crate::supervisor::Fatal(format!(
+                        "oauth service denied access with error code: {status} and body: {error:?}"
+                    ))
+                    .into());
+                }
                 eyre::bail!(
                     "oauth service returned error code: {} with body: {:?}",
                     status,
@@ -245,11 +266,13 @@ pub async fn get_access_token(
         let discord_http = serenity::http::HttpBuilder::without_token()
             .client(client.clone())
             .build();
-        let webhook = serenity::model::webhook::Webhook::from_url(
-            &discord_http,
-            opts.discord_webhook.as_str(),
-        )
-        .await?;
+        let Some(ref url) = opts.discord_webhook else {
+            eyre::bail!(
+                "the device code flow posts the code to `--discord-webhook`, which is not set"
+            );
+        };
+        let webhook =
+            serenity::model::webhook::Webhook::from_url(&discord_http, url.secret()).await?;
         get_dcf_token(
             client,
             &discord_http,
@@ -263,6 +286,9 @@ pub async fn get_access_token(
                 twitch_oauth2::Scope::ModeratorReadModerators,
                 twitch_oauth2::Scope::ModeratorReadVips,
                 twitch_oauth2::Scope::ModeratorReadWarnings,
+                twitch_oauth2::Scope::ModeratorReadSuspiciousUsers,
+                twitch_oauth2::Scope::ModeratorReadShieldMode,
+                twitch_oauth2::Scope::UserReadChat,
             ],
             id.clone(),
             secret.clone(),
@@ -274,96 +300,433 @@ pub async fn get_access_token(
     }
 }
 
+/// Get the channels to monitor from the options. The first one is `--channel-id`, `--channel-login` or the owner of the token.
+pub async fn resolve_channels(
+    client: &HelixClient<'static, reqwest::Client>,
+    opts: &crate::Opts,
+    access_token: &UserToken,
+) -> Result<Vec<Channel>, eyre::Report> {
+    // if env:CHANNEL_ID or env:CHANNEL_LOGIN are not set, then assume we're using the token owner as channel
+    let (channel_id, channel_login) = if let Some(ref id) = opts.channel_id {
+        // use access token to fetch broadcaster login
+        (
+            id.clone().into(),
+            client
+                .get_user_from_id(id, access_token)
+                .await
+                .wrap_err("could not get user from id")?
+                .ok_or_else(|| eyre::eyre!("there is no user id {}", &id))?
+                .login,
+        )
+    } else if let Some(ref login) = opts.channel_login {
+        // use access token to fetch broadcaster id
+        (
+            client
+                .get_user_from_login(login, access_token)
+                .await
+                .wrap_err("could not get user from login")?
+                .ok_or_else(|| eyre::eyre!("there is no user with login name {}", &login))?
+                .id,
+            login.clone().into(),
+        )
+    } else {
+        // FIXME: Use the same client?
+        tracing::info!("Using the same user_id as token for channel id");
+        (
+            access_token
+                .user_id()
+                .ok_or_else(|| eyre::eyre!("no user id attached to token"))?
+                .to_owned(),
+            access_token
+                .login()
+                .ok_or_else(|| eyre::eyre!("no user login attached to token"))?
+                .into(),
+        )
+    };
+    let mut channels = vec![Channel {
+        id: channel_id.clone(),
+        login: channel_login.clone(),
+    }];
+    for login in &opts.config_file.channels {
+        let user = client
+            .get_user_from_login(login, access_token)
+            .await
+            .wrap_err("could not get user from login")?
+            .ok_or_else(|| eyre::eyre!("there is no user with login name {}", &login))?;
+        if !channels.iter().any(|c| c.id == user.id) {
+            channels.push(Channel {
+                id: user.id,
+                login: user.login,
+            });
+        }
+    }
+    Ok(channels)
+}
+
 impl Subscriber {
-    #[tracing::instrument(skip(opts))]
-    pub async fn new(client: &reqwest::Client, opts: &crate::Opts) -> Result<Self, eyre::Report> {
+    #[tracing::instrument(skip(opts, health))]
+    pub async fn new(
+        client: &reqwest::Client,
+        opts: &crate::Opts,
+        health: Arc<crate::health::Health>,
+    ) -> Result<Self, eyre::Report> {
         let access_token = get_access_token(client, opts)
             .await
             .context("could not get access token")?;
+        health.set_token_expiry(access_token.expires_in());
         let token_user_id = access_token
             .validate_token(client)
             .await?
             .user_id
             .ok_or_else(|| eyre::eyre!("no user id found for oauth2 token, this is a bug"))?;
-        // if env:CHANNEL_ID or env:CHANNEL_LOGIN are not set, then assume we're using the token owner as channel
-        let (channel_id, channel_login) = if let Some(ref id) = opts.channel_id {
-            // use access token to fetch broadcaster login
-            (
-                id.clone().into(),
-                twitch_api::HelixClient::with_client(client.clone())
-                    .get_user_from_id(id, &access_token)
-                    .await
-                    .wrap_err("could not get user from id")?
-                    .ok_or_else(|| eyre::eyre!("there is no user id {}", &id))?
-                    .login,
-            )
-        } else if let Some(ref login) = opts.channel_login {
-            // use access token to fetch broadcaster id
-            (
-                twitch_api::HelixClient::with_client(client.clone())
-                    .get_user_from_login(login, &access_token)
-                    .await
-                    .wrap_err("could not get user from login")?
-                    .ok_or_else(|| eyre::eyre!("there is no user with login name {}", &login))?
-                    .id,
-                login.clone().into(),
-            )
-        } else {
-            // FIXME: Use the same client?
-            tracing::info!("Using the same user_id as token for channel id");
-            (
-                token_user_id.clone(),
-                access_token
-                    .login()
-                    .ok_or_else(|| eyre::eyre!("no user login attached to token"))?
-                    .into(),
-            )
-        };
+        let helix = twitch_api::HelixClient::with_client(client.clone());
+        let channels = resolve_channels(&helix, opts, &access_token).await?;
+        let Channel {
+            id: channel_id,
+            login: channel_login,
+        } = channels[0].clone();
         tracing::info!("successfully retrieved token and user info");
         Ok(Subscriber {
             access_token,
             channel_id,
             channel_login,
+            channels,
             token_id: token_user_id,
-            channel: sync::broadcast::channel(16).0,
+            channel: Default::default(),
             client: client.clone(),
+            health,
         })
     }
 
-    #[tracing::instrument(name = "subscriber", skip(self, opts), fields(
+    #[tracing::instrument(name = "subscriber", skip(self, reload, shutdown), fields(
         self.channel_id = %self.channel_id,
         self.channel_login = %self.channel_login,
         self.token_id = %self.token_id,
     ))]
-    pub async fn run(&self, opts: &crate::Opts) -> Result<(), eyre::Report> {
+    pub async fn run(
+        &self,
+        reload: sync::watch::Receiver<Arc<crate::Opts>>,
+        shutdown: &crate::shutdown::Shutdown,
+    ) -> Result<(), eyre::Report> {
         let client = twitch_api::HelixClient::with_client(self.client.clone());
-        let mut connect_url = twitch_api::TWITCH_EVENTSUB_WEBSOCKET_URL.clone();
-        connect_url.set_query(Some("keepalive_timeout_seconds=30"));
-
-        let websocket = WebsocketClient {
-            session_id: None,
-            token: Arc::new(Mutex::new(self.access_token.clone())),
+        let mut websocket = WebsocketClient::new(
             client,
-            connect_url,
-            keepalive_timeout_seconds: 10,
-            chats: vec![self.channel_id.clone()],
+            self.access_token.clone(),
+            self.channels.clone(),
+            self.health.clone(),
+        );
+        let (lookup, target_info, history, roster_interval, bans) = {
+            let opts = reload.borrow();
+            websocket.require_chat_messages = opts.chat_context > 0;
+            (
+                crate::users::UserLookup::new(
+                    twitch_api::HelixClient::with_client(self.client.clone()),
+                    websocket.token.clone(),
+                    opts.target_info_ttl,
+                ),
+                !opts.no_target_info,
+                (opts.chat_context > 0).then(|| crate::chat::ChatHistory::new(opts.chat_context)),
+                opts.roster_interval,
+                opts.bans_file.clone().map(crate::bans::Bans::load),
+            )
+        };
+        let helix = twitch_api::HelixClient::with_client(self.client.clone());
+        let token = websocket.token.clone();
+        let warnings = crate::chat::PendingWarnings::default();
+        let state = ChannelState {
+            settings: Default::default(),
+            roster: Default::default(),
+            streams: Default::default(),
+            shields: Default::default(),
+            bans,
+        };
+        let missed = state
+            .load(&helix, &token, &self.token_id, &self.channels)
+            .await;
+        // channels added or removed by reloading the config
+        let mut changed = websocket.channels.subscribe();
+        let channel_changes = async {
+            for event in missed {
+                // fails only when shutting down
+                let _ = self.channel.send(event).await;
+            }
+            let mut channels = self.channels.clone();
+            while changed.changed().await.is_ok() {
+                let current = changed.borrow_and_update().clone();
+                for channel in channels.iter().filter(|c| !current.contains(c)) {
+                    state.remove(&channel.id).await;
+                }
+                let added = current
+                    .iter()
+                    .filter(|c| !channels.contains(c))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for event in state.load(&helix, &token, &self.token_id, &added).await {
+                    let _ = self.channel.send(event).await;
+                }
+                channels = current;
+            }
+            std::future::pending::<()>().await
+        };
+        let channels = websocket.channels.subscribe();
+        let roster_posts = async {
+            let Some(period) = roster_interval else {
+                return std::future::pending::<()>().await;
+            };
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                let current = channels.borrow().clone();
+                for channel in &current {
+                    if let Some(event) = state.roster.event(channel_user(channel)) {
+                        // fails only when shutting down
+                        let _ = self.channel.send(event).await;
+                    }
+                }
+            }
         };
 
-        websocket
+        let events = websocket
             .run(
                 |event, timestamp| async {
-                    let Some(event) = Events::new(event, timestamp) else {
-                        return Ok(());
+                    let mut event = match Events::new(event, timestamp) {
+                        Some(Events::ChannelModerateV2(p, timestamp)) => {
+                            ModerationEvent::from_moderate(&p, &timestamp)
+                        }
+                        Some(Events::ChannelUnbanRequestCreateV1(p, timestamp)) => {
+                            let mut event =
+                                ModerationEvent::from_unban_request_create(&p, &timestamp);
+                            match crate::users::get_ban(
+                                &helix,
+                                &token,
+                                &p.broadcaster_user_id,
+                                &p.user_id,
+                            )
+                            .await
+                            {
+                                Ok(ban) => event.ban = ban,
+                                Err(e) => tracing::warn!(error = ?e, user = %p.user_login, "could not get ban"),
+                            }
+                            event
+                        }
+                        Some(Events::ChannelUnbanRequestResolveV1(p, timestamp)) => {
+                            ModerationEvent::from_unban_request_resolve(&p, &timestamp)
+                        }
+                        Some(Events::ChannelSuspiciousUserMessageV1(p, timestamp)) => {
+                            let mut event =
+                                ModerationEvent::from_suspicious_user_message(&p, &timestamp);
+                            if let Err(e) = lookup.resolve(&mut event.shared_ban_channels).await {
+                                tracing::warn!(error = ?e, "could not look up shared ban channels");
+                            }
+                            event
+                        }
+                        Some(Events::ChannelSuspiciousUserUpdateV1(p, timestamp)) => {
+                            ModerationEvent::from_suspicious_user_update(&p, &timestamp)
+                        }
+                        Some(Events::ChannelShieldModeBeginV1(p, timestamp)) => {
+                            ModerationEvent::from_shield_mode_begin(&p, &timestamp)
+                        }
+                        Some(Events::ChannelShieldModeEndV1(p, timestamp)) => {
+                            ModerationEvent::from_shield_mode_end(&p, &timestamp)
+                        }
+                        Some(Events::ChannelChatMessageV1(p, timestamp)) => {
+                            let ignored = warnings
+                                .chatted(&p.broadcaster_user_id, &p.chatter_user_id)
+                                .then(|| ModerationEvent::from_ignored_warning(&p, &timestamp));
+                            if let Some(ref history) = history {
+                                history.push(
+                                    &p.broadcaster_user_id,
+                                    &p.chatter_user_id,
+                                    ChatMessage {
+                                        timestamp: timestamp.as_str().to_owned(),
+                                        message_id: p.message_id.as_str().to_owned(),
+                                        text: p.message.text,
+                                    },
+                                );
+                            }
+                            match ignored {
+                                Some(event) => event,
+                                None => return Ok(()),
+                            }
+                        }
+                        Some(Events::ChannelWarningAcknowledgeV1(p, timestamp)) => {
+                            ModerationEvent::from_warning_acknowledge(&p, &timestamp)
+                        }
+                        Some(Events::ChannelChatSettingsUpdateV1(p, timestamp)) => {
+                            ModerationEvent::from_chat_settings_update(&p, &timestamp)
+                        }
+                        Some(Events::ChannelModeratorAddV1(p, timestamp)) => {
+                            ModerationEvent::from_moderator_add(&p, &timestamp)
+                        }
+                        Some(Events::ChannelModeratorRemoveV1(p, timestamp)) => {
+                            ModerationEvent::from_moderator_remove(&p, &timestamp)
+                        }
+                        Some(Events::ChannelVipAddV1(p, timestamp)) => {
+                            ModerationEvent::from_vip_add(&p, &timestamp)
+                        }
+                        Some(Events::ChannelVipRemoveV1(p, timestamp)) => {
+                            ModerationEvent::from_vip_remove(&p, &timestamp)
+                        }
+                        Some(Events::StreamOnlineV1(p, _)) => ModerationEvent::from_stream_online(&p),
+                        Some(Events::StreamOfflineV1(p, timestamp)) => {
+                            ModerationEvent::from_stream_offline(&p, &timestamp)
+                        }
+                        None => return Ok(()),
                     };
-                    self.channel
-                        .send(event)
-                        .map_err(|_| eyre::eyre!("could not send event"))?;
+                    crate::metrics::EVENTS_RECEIVED
+                        .with_label_values(&[&event.kind, &event.channel.login])
+                        .inc();
+                    if target_info {
+                        lookup.enrich(&mut event).await;
+                    }
+                    if let Some(ref history) = history {
+                        history.enrich(&mut event);
+                    }
+                    warnings.update(&event);
+                    if !state.settings.observe(&mut event) || !state.roster.observe(&event) {
+                        return Ok(());
+                    }
+                    state.streams.observe(&mut event);
+                    state.shields.observe(&mut event);
+                    if let Some(ref bans) = state.bans {
+                        bans.observe(&event);
+                    }
+                    self.channel.send(event).await?;
                     Ok(())
                 },
-                opts,
-            )
-            .await?;
-        Ok(())
+                reload,
+                shutdown,
+            );
+        let persist_bans = async {
+            match state.bans {
+                Some(ref bans) => bans.persist().await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            res = events => res,
+            _ = roster_posts => Ok(()),
+            _ = channel_changes => Ok(()),
+            _ = persist_bans => Ok(()),
+        };
+        // no more events, the sinks stop once they sent what is queued
+        self.channel.close();
+        if let Some(ref bans) = state.bans {
+            bans.save().await;
+        }
+        result
+    }
+}
+
+/// Twitch closed the websocket, e.g because the session wasn't used or the bot fell behind. Restarting reconnects.
+#[derive(Debug)]
+pub struct Closed(pub Option<tungstenite::protocol::CloseFrame>);
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(ref frame) => write!(
+                f,
+                "connection was closed by twitch with code {}: {}",
+                frame.code, frame.reason
+            ),
+            None => f.write_str("connection was closed by twitch"),
+        }
+    }
+}
+
+impl std::error::Error for Closed {}
+
+/// What is known about the monitored channels, to fill in what their events leave out, by channel id.
+struct ChannelState {
+    /// What chat settings were before they change
+    settings: crate::settings::KnownSettings,
+    /// Who is a moderator or vip, to post changes made on the dashboard only once
+    roster: crate::roster::Roster,
+    /// Whether channels are live, to tag events with the stream
+    streams: crate::stream::Streams,
+    /// Bans, timeouts and deletes while Shield Mode is on
+    shields: crate::shield::Shields,
+    /// Banned users, to post bans and unbans missed while the bot was down
+    bans: Option<crate::bans::Bans>,
+}
+
+impl ChannelState {
+    /// Get the chat settings, moderators and vips, live streams and banned users of `channels`.
+    ///
+    /// Returns the bans and unbans missed since the channels were last monitored. Failing is logged,
+    /// the channels are still monitored but their events lack what couldn't be fetched.
+    async fn load(
+        &self,
+        helix: &HelixClient<'static, reqwest::Client>,
+        token: &Mutex<UserToken>,
+        token_id: &types::UserIdRef,
+        channels: &[Channel],
+    ) -> Vec<ModerationEvent> {
+        if channels.is_empty() {
+            return vec![];
+        }
+        {
+            let token = token.lock().await;
+            for channel in channels {
+                match helix
+                    .get_chat_settings(&*channel.id, Some(token_id), &*token)
+                    .await
+                {
+                    Ok(s) => self
+                        .settings
+                        .insert(channel.id.as_str(), crate::settings::from_helix(&s)),
+                    Err(e) => {
+                        tracing::warn!(error = ?e, channel = %channel.login, "could not get chat settings")
+                    }
+                }
+            }
+        }
+        for channel in channels {
+            match crate::roster::get_roster(helix, token, &channel.id).await {
+                Ok((moderators, vips)) => self.roster.insert(channel.id.as_str(), moderators, vips),
+                Err(e) => {
+                    tracing::warn!(error = ?e, channel = %channel.login, "could not get moderators and vips")
+                }
+            }
+        }
+        let ids = channels.iter().map(|c| &*c.id).collect::<Vec<_>>();
+        match crate::stream::get_live(helix, token, &ids).await {
+            Ok(mut live) => {
+                for channel in channels {
+                    self.streams
+                        .insert(channel.id.as_str(), live.remove(channel.id.as_str()));
+                }
+            }
+            Err(e) => tracing::warn!(error = ?e, "could not get live streams"),
+        }
+        let mut missed = vec![];
+        if let Some(ref bans) = self.bans {
+            let now = types::Timestamp::now();
+            for channel in channels {
+                match crate::bans::get_banned_users(helix, token, &channel.id).await {
+                    Ok(banned) => {
+                        missed.extend(bans.catch_up(&channel_user(channel), banned, &now))
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = ?e, channel = %channel.login, "could not get banned users")
+                    }
+                }
+            }
+        }
+        missed
+    }
+
+    /// Forget a channel that is no longer monitored.
+    async fn remove(&self, channel_id: &types::UserIdRef) {
+        self.settings.remove(channel_id.as_str());
+        self.roster.remove(channel_id.as_str());
+        self.streams.remove(channel_id.as_str());
+        self.shields.remove(channel_id.as_str());
+        if let Some(ref bans) = self.bans {
+            bans.remove(channel_id.as_str());
+        }
     }
 }
 
@@ -378,10 +741,38 @@ pub struct WebsocketClient {
     pub connect_url: url::Url,
     /// Chats to connect to.
     pub chats: Vec<twitch_api::types::UserId>,
+    /// The monitored channels, sent again when reloading the config changes them
+    pub channels: sync::watch::Sender<Vec<Channel>>,
+    /// Health of the connection
+    pub health: Arc<crate::health::Health>,
+    /// Fail when `channel.chat.message` can't be subscribed to, instead of only missing `warning_ignored`
+    pub require_chat_messages: bool,
     keepalive_timeout_seconds: i64,
 }
 
 impl WebsocketClient {
+    /// A client for the twitch eventsub websocket, subscribing to `channel.moderate` and other moderation events in `chats`.
+    pub fn new(
+        client: HelixClient<'static, reqwest::Client>,
+        token: UserToken,
+        channels: Vec<Channel>,
+        health: Arc<crate::health::Health>,
+    ) -> Self {
+        let mut connect_url = twitch_api::TWITCH_EVENTSUB_WEBSOCKET_URL.clone();
+        connect_url.set_query(Some("keepalive_timeout_seconds=30"));
+        WebsocketClient {
+            session_id: None,
+            token: Arc::new(Mutex::new(token)),
+            client,
+            connect_url,
+            keepalive_timeout_seconds: 10,
+            chats: channels.iter().map(|c| c.id.clone()).collect(),
+            channels: sync::watch::Sender::new(channels),
+            health,
+            require_chat_messages: false,
+        }
+    }
+
     /// Connect to the websocket and return the stream
     async fn connect(
         &self,
@@ -408,10 +799,13 @@ impl WebsocketClient {
             tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
         >,
     ) -> Result<(), eyre::Report> {
+        self.session_id = None;
+        self.health.set_session(None);
         {
             let mut token = self.token.lock().await;
             if token.expires_in() < std::time::Duration::from_secs(60) {
                 *token = get_access_token(&self.client.clone_client(), opts).await?;
+                self.health.set_token_expiry(token.expires_in());
             }
         }
         *stream = self
@@ -420,16 +814,53 @@ impl WebsocketClient {
             .context("could not reestablish connection")?;
         Ok(())
     }
+    /// Close the connection, optionally deleting the subscriptions made on this session first.
+    async fn close(
+        &mut self,
+        opts: &crate::Opts,
+        stream: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+    ) {
+        if let (true, Some(session_id)) = (
+            opts.delete_subscriptions_on_shutdown,
+            self.session_id.take(),
+        ) {
+            let token = self.token.lock().await;
+            match self.session_subscriptions(&session_id, &token).await {
+                Ok(subs) => {
+                    for sub in subs {
+                        if let Err(e) = self
+                            .client
+                            .delete_eventsub_subscription(&sub.id, &*token)
+                            .await
+                        {
+                            tracing::warn!(error = ?e, id = %sub.id, "could not delete subscription");
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!(error = ?e, "could not get subscriptions to delete"),
+            }
+        }
+        if let Err(e) = stream.close(None).await {
+            tracing::warn!(error = ?e, "could not close connection cleanly");
+        }
+        self.health.set_session(None);
+        tracing::info!("connection closed");
+    }
+
     /// Run the websocket subscriber
     #[tracing::instrument(name = "subscriber", skip_all, fields())]
     pub async fn run<Fut>(
         mut self,
         mut event_fn: impl FnMut(Event, types::Timestamp) -> Fut,
-        opts: &crate::Opts,
+        mut reload: sync::watch::Receiver<Arc<crate::Opts>>,
+        shutdown: &crate::shutdown::Shutdown,
     ) -> Result<(), eyre::Report>
     where
         Fut: std::future::Future<Output = Result<(), eyre::Report>>,
     {
+        let mut opts = reload.borrow_and_update().clone();
         // Establish the stream
         let mut s = self
             .connect()
@@ -439,32 +870,54 @@ impl WebsocketClient {
         // Loop over the stream, processing messages as they come in.
         loop {
             // respect keepalive timeout
-            match tokio::time::timeout(
-                // add 10% offset to keepalive
-                std::time::Duration::from_millis(self.keepalive_timeout_seconds as u64 * 1100),
-                futures::StreamExt::next(&mut s),
-            )
-            .await
-            {
+            let next = tokio::select! {
+                next = tokio::time::timeout(
+                    // add 10% offset to keepalive
+                    std::time::Duration::from_millis(self.keepalive_timeout_seconds as u64 * 1100),
+                    futures::StreamExt::next(&mut s),
+                ) => next,
+                _ = shutdown.wait() => {
+                    self.close(&opts, &mut s).await;
+                    return Ok(());
+                }
+                Ok(()) = reload.changed() => {
+                    opts = reload.borrow_and_update().clone();
+                    if let Err(e) = self.update_chats(&opts).await {
+                        tracing::error!(error = ?e, "could not update the monitored channels");
+                    }
+                    continue;
+                }
+            };
+            match next {
                 Err(_) => {
                     tracing::warn!(
                         "connection has not responded in {}s, reconnecting",
                         self.keepalive_timeout_seconds
                     );
-                    self.reconnect(opts, &mut s).await?;
+                    crate::metrics::WEBSOCKET_RECONNECTS
+                        .with_label_values(&["keepalive_timeout"])
+                        .inc();
+                    self.reconnect(&opts, &mut s).await?;
                 }
                 Ok(None) => {
                     tracing::warn!("connection has ended unexpectedly, reconnecting",);
-                    self.reconnect(opts, &mut s).await?;
+                    crate::metrics::WEBSOCKET_RECONNECTS
+                        .with_label_values(&["stream_ended"])
+                        .inc();
+                    self.reconnect(&opts, &mut s).await?;
                 }
                 Ok(Some(msg)) => {
+                    self.health.keepalive();
                     let span = tracing::debug_span!("message received", raw_message = ?msg);
                     let msg = match msg {
                         Err(tungstenite::Error::Protocol(
                             tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
                         )) => {
                             tracing::warn!("connection was sent an unexpected frame or was reset, reestablishing it");
-                            self.reconnect(opts, &mut s).await?;
+                            crate::metrics::WEBSOCKET_RECONNECTS
+                                .with_label_values(&["protocol_reset"])
+                                .inc();
+                            self.reconnect(&opts, &mut s).await?;
                             continue;
                         }
                         _ => msg.context("unexpected error message")?,
@@ -516,7 +969,7 @@ impl WebsocketClient {
                     _ => Ok(()),
                 }
             }
-            tungstenite::Message::Close(_) => todo!(),
+            tungstenite::Message::Close(frame) => Err(Closed(frame).into()),
             _ => Ok(()),
         }
     }
@@ -524,45 +977,21 @@ impl WebsocketClient {
     async fn process_welcome_message(&mut self, data: SessionData<'_>) -> Result<(), eyre::Report> {
         tracing::info!("connected to twitch chat");
         self.session_id = Some(data.id.to_string());
+        self.health.set_session(self.session_id.clone());
         if let Some(url) = data.reconnect_url {
             self.connect_url = url.parse()?;
         }
         if let Some(kt) = data.keepalive_timeout_seconds {
             self.keepalive_timeout_seconds = kt;
         }
+        self.health
+            .set_keepalive_timeout(std::time::Duration::from_millis(
+                self.keepalive_timeout_seconds as u64 * 1100,
+            ));
         let token = self.token.lock().await;
-        let transport = eventsub::Transport::websocket(data.id.clone());
-        for broadcaster_id in &self.chats {
-            let token_user_id = token.user_id().unwrap().to_owned();
-            let subs: Vec<_> = self
-                .client
-                .get_eventsub_subscriptions(Some(eventsub::Status::Enabled), None, None, &*token)
-                .map_ok(|r| {
-                    futures::stream::iter(
-                        r.subscriptions
-                            .into_iter()
-                            .filter(|s| {
-                                s.transport
-                                    .as_websocket()
-                                    .is_some_and(|t| t.session_id == data.id)
-                            })
-                            .map(Ok::<_, eyre::Report>),
-                    )
-                })
-                .try_flatten()
-                .try_collect()
-                .await?;
-            if !subs.is_empty() {
-                continue;
-            }
-            // if you update the scopes needed, make sure to update do_dcf_flow() as well
-            let moderate = eventsub::channel::ChannelModerateV2::new(
-                broadcaster_id.clone(),
-                token_user_id.clone(),
-            );
-            self.client
-                .create_eventsub_subscription(moderate, transport.clone(), &*token)
-                .await?;
+        let subs = self.session_subscriptions(&data.id, &token).await?;
+        for broadcaster_id in unsubscribed(&self.chats, &subs) {
+            self.subscribe(broadcaster_id, &data.id, &token).await?;
             // let automod_update = eventsub::automod::AutomodTermsUpdateV1::new(
             //     broadcaster_id.clone(),
             //     token_user_id.clone(),
@@ -580,6 +1009,277 @@ impl WebsocketClient {
         }
         Ok(())
     }
+
+    /// Subscribe to the events of a channel on a session
+    async fn subscribe(
+        &self,
+        broadcaster_id: &types::UserIdRef,
+        session_id: &str,
+        token: &UserToken,
+    ) -> Result<(), eyre::Report> {
+        let token_user_id = token.user_id().unwrap().to_owned();
+        let transport = eventsub::Transport::websocket(session_id);
+        // if you update the scopes needed, make sure to update do_dcf_flow() as well
+        let moderate = eventsub::channel::ChannelModerateV2::new(
+            broadcaster_id.to_owned(),
+            token_user_id.clone(),
+        );
+        self.client
+            .create_eventsub_subscription(moderate, transport.clone(), token)
+            .await?;
+        self.subscribe_optional(
+            eventsub::channel::ChannelUnbanRequestCreateV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:unban_requests",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelUnbanRequestResolveV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:unban_requests",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelSuspiciousUserMessageV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:suspicious_users",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelSuspiciousUserUpdateV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:suspicious_users",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelShieldModeBeginV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:shield_mode",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelShieldModeEndV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:shield_mode",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelWarningAcknowledgeV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:warnings",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelChatSettingsUpdateV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "user:read:chat",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelModeratorAddV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:moderators",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelModeratorRemoveV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:moderators",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelVipAddV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:vips",
+        )
+        .await;
+        self.subscribe_optional(
+            eventsub::channel::ChannelVipRemoveV1::new(
+                broadcaster_id.to_owned(),
+                token_user_id.clone(),
+            ),
+            transport.clone(),
+            token,
+            "moderator:read:vips",
+        )
+        .await;
+        // stream.online and stream.offline need no scope
+        self.client
+            .create_eventsub_subscription(
+                eventsub::stream::StreamOnlineV1::broadcaster_user_id(broadcaster_id.to_owned()),
+                transport.clone(),
+                token,
+            )
+            .await
+            .wrap_err("could not subscribe to stream.online")?;
+        self.client
+            .create_eventsub_subscription(
+                eventsub::stream::StreamOfflineV1::broadcaster_user_id(broadcaster_id.to_owned()),
+                transport.clone(),
+                token,
+            )
+            .await
+            .wrap_err("could not subscribe to stream.offline")?;
+        // read for `--chat-context` and to notice warned users chatting
+        let chat =
+            eventsub::channel::ChannelChatMessageV1::new(broadcaster_id.to_owned(), token_user_id);
+        if self.require_chat_messages {
+            self.client
+                .create_eventsub_subscription(chat, transport, token)
+                .await
+                .wrap_err("could not subscribe to chat messages, does the token have the `user:read:chat` scope?")?;
+        } else {
+            self.subscribe_optional(chat, transport, token, "user:read:chat")
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Subscribe to events that are posted when the token allows it. Failing is logged, as older tokens might lack `scope`.
+    async fn subscribe_optional<E>(
+        &self,
+        subscription: E,
+        transport: eventsub::Transport,
+        token: &UserToken,
+        scope: &str,
+    ) where
+        E: eventsub::EventSubscription + Send,
+    {
+        if let Err(e) = self
+            .client
+            .create_eventsub_subscription(subscription, transport, token)
+            .await
+        {
+            tracing::warn!(
+                error = ?e,
+                event_type = %E::EVENT_TYPE,
+                "could not subscribe, does the token have the `{scope}` scope?"
+            );
+        }
+    }
+
+    /// Change the monitored channels to the ones in `opts`, adding and removing subscriptions on the current session.
+    async fn update_chats(&mut self, opts: &crate::Opts) -> Result<(), eyre::Report> {
+        let token = self.token.lock().await;
+        let channels = resolve_channels(&self.client, opts, &token).await?;
+        let chats = channels.iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+        if chats == self.chats {
+            return Ok(());
+        }
+        // without a session, the new chats are subscribed to when the welcome message arrives
+        if let Some(ref session_id) = self.session_id {
+            let subs = self.session_subscriptions(session_id, &token).await?;
+            for removed in self.chats.iter().filter(|c| !chats.contains(c)) {
+                tracing::info!(broadcaster_id = %removed, "no longer monitoring channel");
+                for sub in subs
+                    .iter()
+                    .filter(|s| subscribed_broadcaster(s) == Some(removed.as_str()))
+                {
+                    self.client
+                        .delete_eventsub_subscription(&sub.id, &*token)
+                        .await?;
+                }
+            }
+            for added in chats.iter().filter(|c| !self.chats.contains(c)) {
+                tracing::info!(broadcaster_id = %added, "now monitoring channel");
+                self.subscribe(added, session_id, &token).await?;
+            }
+        }
+        self.chats = chats;
+        self.channels.send_replace(channels);
+        Ok(())
+    }
+
+    /// Get the enabled subscriptions made on a session
+    async fn session_subscriptions(
+        &self,
+        session_id: &str,
+        token: &UserToken,
+    ) -> Result<Vec<eventsub::EventSubSubscription>, eyre::Report> {
+        self.client
+            .get_eventsub_subscriptions(Some(eventsub::Status::Enabled), None, None, token)
+            .map_ok(|r| {
+                futures::stream::iter(
+                    r.subscriptions
+                        .into_iter()
+                        .filter(|s| {
+                            s.transport
+                                .as_websocket()
+                                .is_some_and(|t| t.session_id == session_id)
+                        })
+                        .map(Ok::<_, eyre::Report>),
+                )
+            })
+            .try_flatten()
+            .try_collect()
+            .await
+    }
+}
+
+/// The channel a subscription is for.
+fn subscribed_broadcaster(sub: &eventsub::EventSubSubscription) -> Option<&str> {
+    sub.condition
+        .get("broadcaster_user_id")
+        .and_then(|v| v.as_str())
+}
+
+/// The chats without any of the subscriptions in `subs`, e.g on a new session or after the chats changed.
+fn unsubscribed<'a>(
+    chats: &'a [types::UserId],
+    subs: &[eventsub::EventSubSubscription],
+) -> Vec<&'a types::UserId> {
+    chats
+        .iter()
+        .filter(|chat| {
+            !subs
+                .iter()
+                .any(|s| subscribed_broadcaster(s) == Some(chat.as_str()))
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -596,6 +1296,66 @@ pub enum Events {
         <eventsub::channel::ChannelModerateV2 as eventsub::EventSubscription>::Payload,
         types::Timestamp,
     ),
+    ChannelChatMessageV1(
+        <eventsub::channel::ChannelChatMessageV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelUnbanRequestCreateV1(
+        <eventsub::channel::ChannelUnbanRequestCreateV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelUnbanRequestResolveV1(
+        <eventsub::channel::ChannelUnbanRequestResolveV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelSuspiciousUserMessageV1(
+        <eventsub::channel::ChannelSuspiciousUserMessageV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelSuspiciousUserUpdateV1(
+        <eventsub::channel::ChannelSuspiciousUserUpdateV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelShieldModeBeginV1(
+        <eventsub::channel::ChannelShieldModeBeginV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelShieldModeEndV1(
+        <eventsub::channel::ChannelShieldModeEndV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelWarningAcknowledgeV1(
+        <eventsub::channel::ChannelWarningAcknowledgeV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelChatSettingsUpdateV1(
+        <eventsub::channel::ChannelChatSettingsUpdateV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelModeratorAddV1(
+        <eventsub::channel::ChannelModeratorAddV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelModeratorRemoveV1(
+        <eventsub::channel::ChannelModeratorRemoveV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelVipAddV1(
+        <eventsub::channel::ChannelVipAddV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    ChannelVipRemoveV1(
+        <eventsub::channel::ChannelVipRemoveV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    StreamOnlineV1(
+        <eventsub::stream::StreamOnlineV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
+    StreamOfflineV1(
+        <eventsub::stream::StreamOfflineV1 as eventsub::EventSubscription>::Payload,
+        types::Timestamp,
+    ),
 }
 
 impl Events {
@@ -613,8 +1373,317 @@ impl Events {
                 message: eventsub::Message::Notification(p),
                 ..
             }) => Events::ChannelModerateV2(p, timestamp),
+            Event::ChannelChatMessageV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelChatMessageV1(p, timestamp),
+            Event::ChannelUnbanRequestCreateV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelUnbanRequestCreateV1(p, timestamp),
+            Event::ChannelUnbanRequestResolveV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelUnbanRequestResolveV1(p, timestamp),
+            Event::ChannelSuspiciousUserMessageV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelSuspiciousUserMessageV1(p, timestamp),
+            Event::ChannelSuspiciousUserUpdateV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelSuspiciousUserUpdateV1(p, timestamp),
+            Event::ChannelShieldModeBeginV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelShieldModeBeginV1(p, timestamp),
+            Event::ChannelShieldModeEndV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelShieldModeEndV1(p, timestamp),
+            Event::ChannelWarningAcknowledgeV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelWarningAcknowledgeV1(p, timestamp),
+            Event::ChannelChatSettingsUpdateV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelChatSettingsUpdateV1(p, timestamp),
+            Event::ChannelModeratorAddV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelModeratorAddV1(p, timestamp),
+            Event::ChannelModeratorRemoveV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelModeratorRemoveV1(p, timestamp),
+            Event::ChannelVipAddV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelVipAddV1(p, timestamp),
+            Event::ChannelVipRemoveV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::ChannelVipRemoveV1(p, timestamp),
+            Event::StreamOnlineV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::StreamOnlineV1(p, timestamp),
+            Event::StreamOfflineV1(eventsub::Payload {
+                message: eventsub::Message::Notification(p),
+                ..
+            }) => Events::StreamOfflineV1(p, timestamp),
             _ => return None,
         };
         Some(event)
     }
 }
+
+/// A monitored channel as a user, twitch doesn't give its display name here.
+fn channel_user(channel: &Channel) -> crate::event::User {
+    crate::event::User {
+        id: channel.id.as_str().to_owned(),
+        login: channel.login.as_str().to_owned(),
+        name: channel.login.as_str().to_owned(),
+    }
+}
+
+/// Name of a moderator action, e.g `ban` or `emoteonlyoff`
+pub fn action_kind(action: &eventsub::channel::moderate::ActionV2) -> &'static str {
+    use eventsub::channel::moderate::ActionV2;
+    match action {
+        ActionV2::Ban(_) => "ban",
+        ActionV2::Timeout(_) => "timeout",
+        ActionV2::Unban(_) => "unban",
+        ActionV2::Untimeout(_) => "untimeout",
+        ActionV2::Clear => "clear",
+        ActionV2::EmoteOnly => "emoteonly",
+        ActionV2::EmoteOnlyOff => "emoteonlyoff",
+        ActionV2::Followers(_) => "followers",
+        ActionV2::FollowersOff => "followersoff",
+        ActionV2::Uniquechat => "uniquechat",
+        ActionV2::UniquechatOff => "uniquechatoff",
+        ActionV2::Slow(_) => "slow",
+        ActionV2::SlowOff => "slowoff",
+        ActionV2::Subscribers => "subscribers",
+        ActionV2::SubscribersOff => "subscribersoff",
+        ActionV2::Unraid(_) => "unraid",
+        ActionV2::Delete(_) => "delete",
+        ActionV2::Unvip(_) => "unvip",
+        ActionV2::Vip(_) => "vip",
+        ActionV2::Raid(_) => "raid",
+        ActionV2::AddBlockedTerm(_) => "add_blocked_term",
+        ActionV2::AddPermittedTerm(_) => "add_permitted_term",
+        ActionV2::RemoveBlockedTerm(_) => "remove_blocked_term",
+        ActionV2::RemovePermittedTerm(_) => "remove_permitted_term",
+        ActionV2::Mod(_) => "mod",
+        ActionV2::Unmod(_) => "unmod",
+        ActionV2::ApproveUnbanRequest(_) => "approve_unban_request",
+        ActionV2::DenyUnbanRequest(_) => "deny_unban_request",
+        ActionV2::Warn(_) => "warn",
+        ActionV2::SharedChatBan(_) => "shared_chat_ban",
+        ActionV2::SharedChatUnban(_) => "shared_chat_unban",
+        ActionV2::SharedChatTimeout(_) => "shared_chat_timeout",
+        ActionV2::SharedChatUntimeout(_) => "shared_chat_untimeout",
+        ActionV2::SharedChatDelete(_) => "shared_chat_delete",
+        _ => "unknown",
+    }
+}
+
+/// Id, login and display name of the user an action was done on, if any
+pub fn action_target(
+    action: &eventsub::channel::moderate::ActionV2,
+) -> Option<(
+    &types::UserIdRef,
+    &types::UserNameRef,
+    &types::DisplayNameRef,
+)> {
+    use eventsub::channel::moderate::{self, ActionV2};
+    let user = match action {
+        ActionV2::Ban(moderate::Ban {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::SharedChatBan(moderate::SharedChatBan(moderate::Ban {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }))
+        | ActionV2::Timeout(moderate::Timeout {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::SharedChatTimeout(moderate::SharedChatTimeout(moderate::Timeout {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }))
+        | ActionV2::Unban(moderate::Unban {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::SharedChatUnban(moderate::SharedChatUnban(moderate::Unban {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }))
+        | ActionV2::Untimeout(moderate::Untimeout {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::SharedChatUntimeout(moderate::SharedChatUntimeout(moderate::Untimeout {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }))
+        | ActionV2::Delete(moderate::Delete {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::SharedChatDelete(moderate::SharedChatDelete(moderate::Delete {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }))
+        | ActionV2::Vip(moderate::Vip {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Unvip(moderate::Unvip {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Mod(moderate::Mod {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Unmod(moderate::Unmod {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Raid(moderate::Raid {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Unraid(moderate::Unraid {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::ApproveUnbanRequest(moderate::UnbanRequest {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::DenyUnbanRequest(moderate::UnbanRequest {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        })
+        | ActionV2::Warn(moderate::Warn {
+            user_id,
+            user_login,
+            user_name,
+            ..
+        }) => (user_id, user_login, user_name),
+        _ => return None,
+    };
+    Some(user)
+}
+
+/// Reason given for an action, if any
+pub fn action_reason(action: &eventsub::channel::moderate::ActionV2) -> Option<&str> {
+    use eventsub::channel::moderate::{self, ActionV2};
+    match action {
+        ActionV2::Ban(moderate::Ban { reason, .. })
+        | ActionV2::SharedChatBan(moderate::SharedChatBan(moderate::Ban { reason, .. }))
+        | ActionV2::Timeout(moderate::Timeout { reason, .. })
+        | ActionV2::SharedChatTimeout(moderate::SharedChatTimeout(moderate::Timeout {
+            reason,
+            ..
+        }))
+        | ActionV2::Warn(moderate::Warn { reason, .. }) => reason.as_deref(),
+        _ => None,
+    }
+}
+
+/// Body of the chat message an action was done on, if any
+pub fn action_message(action: &eventsub::channel::moderate::ActionV2) -> Option<&str> {
+    use eventsub::channel::moderate::{self, ActionV2};
+    match action {
+        ActionV2::Delete(moderate::Delete { message_body, .. })
+        | ActionV2::SharedChatDelete(moderate::SharedChatDelete(moderate::Delete {
+            message_body,
+            ..
+        })) => Some(message_body.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(broadcaster_id: &str) -> eventsub::EventSubSubscription {
+        serde_json::from_value(serde_json::json!({
+            "id": "f1c2a387-161a-49f9-a165-0f21d7a4e1c4",
+            "status": "enabled",
+            "type": "channel.moderate",
+            "version": "2",
+            "condition": {
+                "broadcaster_user_id": broadcaster_id,
+                "moderator_user_id": "1",
+            },
+            "created_at": "2024-01-01T00:00:00Z",
+            "transport": {
+                "method": "websocket",
+                "session_id": "AQoQexAWVYKSTIu4ec_2VAxyuhAB",
+                "connected_at": "2024-01-01T00:00:00Z",
+            },
+            "cost": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn subscribes_to_every_new_chat() {
+        let chats = [
+            types::UserId::from_static("12826"),
+            types::UserId::from_static("141981764"),
+        ];
+        assert_eq!(unsubscribed(&chats, &[]), [&chats[0], &chats[1]]);
+        // the first chat is subscribed, the second one still needs its subscriptions
+        let subs = [subscription("12826")];
+        assert_eq!(unsubscribed(&chats, &subs), [&chats[1]]);
+        let subs = [subscription("12826"), subscription("141981764")];
+        assert!(unsubscribed(&chats, &subs).is_empty());
+    }
+}
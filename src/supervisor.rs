@@ -0,0 +1,262 @@
+//! Restarting the bot after errors.
+
+use std::time::Duration;
+
+use twitch_api::twitch_oauth2;
+
+/// Marks an error as not recoverable by restarting, e.g bad configuration.
+#[derive(Debug)]
+pub struct Fatal(pub String);
+
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Fatal {}
+
+/// How an error should be handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Restarting will not help, e.g a bad webhook url, revoked authorization or missing scopes.
+    Fatal,
+    /// Restarting might help, e.g network errors, server errors or the websocket being reset.
+    Transient,
+}
+
+impl std::fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorClass::Fatal => f.write_str("fatal"),
+            ErrorClass::Transient => f.write_str("transient"),
+        }
+    }
+}
+
+fn is_auth_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN
+}
+
+/// Classify an error by looking through its chain of causes.
+pub fn classify(err: &eyre::Report) -> ErrorClass {
+    for cause in err.chain() {
+        if cause.downcast_ref::<Fatal>().is_some() {
+            return ErrorClass::Fatal;
+        }
+        if cause.downcast_ref::<crate::subscriber::Closed>().is_some() {
+            return ErrorClass::Transient;
+        }
+        if let Some(serenity::Error::Http(e)) = cause.downcast_ref::<serenity::Error>() {
+            match e {
+                serenity::http::HttpError::InvalidWebhook | serenity::http::HttpError::Url(_) => {
+                    return ErrorClass::Fatal
+                }
+                // the webhook was deleted or the token in the url is wrong
+                e if e
+                    .status_code()
+                    .is_some_and(|s| is_auth_status(s) || s == reqwest::StatusCode::NOT_FOUND) =>
+                {
+                    return ErrorClass::Fatal
+                }
+                _ => {}
+            }
+        }
+        if let Some(twitch_oauth2::tokens::errors::ValidationError::NotAuthorized) =
+            cause.downcast_ref::<twitch_oauth2::tokens::errors::ValidationError<reqwest::Error>>()
+        {
+            return ErrorClass::Fatal;
+        }
+        // creating subscriptions fails like this when the token is missing scopes or the user is not a moderator
+        if let Some(twitch_api::helix::ClientRequestError::HelixRequestPostError(
+            twitch_api::helix::HelixRequestPostError::Error { status, .. },
+        )) = cause.downcast_ref::<twitch_api::helix::ClientRequestError<reqwest::Error>>()
+        {
+            if is_auth_status(*status) {
+                return ErrorClass::Fatal;
+            }
+        }
+    }
+    ErrorClass::Transient
+}
+
+/// Policy for restarting the bot after transient errors.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Give up after this many errors in a row, `None` to never give up.
+    pub max_attempts: Option<u32>,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// If the bot ran this long before failing, the error is not counted as being in a row with earlier errors.
+    pub reset_after: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_opts(opts: &crate::Opts) -> Self {
+        Self {
+            max_attempts: Some(opts.retry_max_attempts).filter(|&a| a != 0),
+            initial_backoff: opts.retry_initial_backoff,
+            max_backoff: opts.retry_max_backoff,
+            reset_after: opts.retry_reset_after,
+        }
+    }
+
+    /// Backoff before the given attempt, starting at 1. Doubles each attempt, with jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        // sleep somewhere between half and the full backoff, so that many bots don't retry at the same time
+        backoff / 2 + backoff.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Current restart state, for logs, metrics and error reports.
+#[derive(Debug, Clone, Default)]
+pub struct RestartState {
+    /// Times the bot has been restarted in total.
+    pub restarts: u64,
+    /// Errors in a row, reset when the bot has been running for long enough.
+    pub attempt: u32,
+    /// Error that started the current run of errors.
+    pub first_error: Option<String>,
+    pub last_error: Option<String>,
+    pub last_class: Option<ErrorClass>,
+}
+
+/// What to do after an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Restart after sleeping for this long.
+    Retry(Duration),
+    GiveUp,
+}
+
+#[derive(Debug)]
+pub struct Supervisor {
+    policy: RetryPolicy,
+    state: RestartState,
+}
+
+/// Format the chain of an error, one cause per line.
+pub fn format_chain(err: &eyre::Report) -> String {
+    let mut error = String::new();
+    for err in err.chain() {
+        error.push_str(&format!("> {err}\n"));
+    }
+    error
+}
+
+impl Supervisor {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            state: RestartState::default(),
+        }
+    }
+
+    pub fn state(&self) -> &RestartState {
+        &self.state
+    }
+
+    /// Record that the bot failed with `err` after running for `ran_for`, and decide what to do.
+    pub fn on_error(&mut self, err: &eyre::Report, ran_for: Duration) -> Decision {
+        let class = classify(err);
+        let error = format_chain(err);
+        if ran_for >= self.policy.reset_after {
+            self.state.attempt = 0;
+        }
+        if self.state.attempt == 0 {
+            self.state.first_error = Some(error.clone());
+        }
+        self.state.attempt += 1;
+        self.state.last_error = Some(error);
+        self.state.last_class = Some(class);
+        crate::metrics::ERRORS
+            .with_label_values(&[&class.to_string()])
+            .inc();
+        crate::metrics::CONSECUTIVE_ERRORS.set(self.state.attempt.into());
+
+        if class == ErrorClass::Fatal {
+            return Decision::GiveUp;
+        }
+        if self
+            .policy
+            .max_attempts
+            .is_some_and(|max| self.state.attempt >= max)
+        {
+            return Decision::GiveUp;
+        }
+        self.state.restarts += 1;
+        crate::metrics::RESTARTS.inc();
+        Decision::Retry(self.policy.backoff(self.state.attempt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(3),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn backoff_is_jittered_and_capped() {
+        let policy = policy();
+        for attempt in 1..10 {
+            let expected = Duration::from_secs(2u64.pow(attempt - 1).min(30));
+            let backoff = policy.backoff(attempt);
+            assert!(
+                backoff >= expected / 2 && backoff <= expected,
+                "{backoff:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn gives_up() {
+        let mut supervisor = Supervisor::new(policy());
+        let transient = eyre::eyre!("connection reset");
+        let fatal = eyre::Report::new(Fatal("bad webhook".to_owned())).wrap_err("could not start");
+
+        assert!(matches!(
+            supervisor.on_error(&transient, Duration::ZERO),
+            Decision::Retry(_)
+        ));
+        assert!(matches!(
+            supervisor.on_error(&transient, Duration::ZERO),
+            Decision::Retry(_)
+        ));
+        assert_eq!(
+            supervisor.on_error(&transient, Duration::ZERO),
+            Decision::GiveUp
+        );
+        // running long enough resets the errors in a row
+        assert!(matches!(
+            supervisor.on_error(&transient, Duration::from_secs(120)),
+            Decision::Retry(_)
+        ));
+        assert_eq!(supervisor.state().attempt, 1);
+        assert_eq!(supervisor.state().restarts, 3);
+
+        assert_eq!(
+            supervisor.on_error(&fatal, Duration::from_secs(120)),
+            Decision::GiveUp
+        );
+        assert_eq!(supervisor.state().last_class, Some(ErrorClass::Fatal));
+    }
+
+    #[test]
+    fn reconnects_when_closed() {
+        let closed =
+            eyre::Report::new(crate::subscriber::Closed(None)).wrap_err("subscriber failed");
+        assert_eq!(classify(&closed), ErrorClass::Transient);
+    }
+}
@@ -0,0 +1,165 @@
+//! Looking up users that actions are done on, see `--no-target-info`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use eyre::WrapErr;
+use tokio::sync::Mutex;
+use twitch_api::twitch_oauth2::UserToken;
+use twitch_api::{helix, types, HelixClient};
+
+use crate::event::{Ban, ModerationEvent, User, UserInfo, CHATTER_ACTIONS};
+
+/// Helix takes at most this many ids in one request
+const MAX_IDS: usize = 100;
+
+/// Looks up users with Helix `get_users`, remembering them for a while.
+pub struct UserLookup {
+    client: HelixClient<'static, reqwest::Client>,
+    token: Arc<Mutex<UserToken>>,
+    ttl: Duration,
+    /// Looked up users by id, and when they were looked up. `None` if the user doesn't exist.
+    cache: std::sync::Mutex<HashMap<types::UserId, (Instant, Option<(User, UserInfo)>)>>,
+}
+
+impl UserLookup {
+    pub fn new(
+        client: HelixClient<'static, reqwest::Client>,
+        token: Arc<Mutex<UserToken>>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            client,
+            token,
+            ttl,
+            cache: Default::default(),
+        }
+    }
+
+    /// Users by id, from the cache if they were looked up less than the ttl ago. The others are looked up
+    /// [`MAX_IDS`] at a time.
+    async fn get_many(
+        &self,
+        ids: &[&types::UserIdRef],
+    ) -> Result<HashMap<types::UserId, Option<(User, UserInfo)>>, eyre::Report> {
+        let mut found = HashMap::new();
+        let mut missing = vec![];
+        {
+            let cache = self.cache.lock().unwrap();
+            for &id in ids {
+                match cache.get(id) {
+                    Some((at, user)) if at.elapsed() < self.ttl => {
+                        found.insert(id.to_owned(), user.clone());
+                    }
+                    _ if !missing.contains(&id) => missing.push(id),
+                    _ => {}
+                }
+            }
+        }
+        for ids in missing.chunks(MAX_IDS) {
+            let request = helix::users::GetUsersRequest::ids(ids);
+            let response = {
+                let token = self.token.lock().await;
+                self.client
+                    .req_get(request, &*token)
+                    .await
+                    .wrap_err("could not get users")?
+            };
+            // users that aren't in the response don't exist
+            let mut looked_up = ids
+                .iter()
+                .map(|&id| (id.to_owned(), None))
+                .collect::<HashMap<_, _>>();
+            for user in response.data {
+                let info = UserInfo {
+                    created_at: user.created_at.as_str().to_owned(),
+                    profile_image_url: user.profile_image_url,
+                    broadcaster_type: match user.broadcaster_type {
+                        Some(types::BroadcasterType::Partner) => "partner",
+                        Some(types::BroadcasterType::Affiliate) => "affiliate",
+                        _ => "",
+                    }
+                    .to_owned(),
+                };
+                let known = User::new(&user.id, &user.login, &user.display_name);
+                looked_up.insert(user.id, Some((known, info)));
+            }
+            let now = Instant::now();
+            let mut cache = self.cache.lock().unwrap();
+            cache.retain(|_, (at, _)| at.elapsed() < self.ttl);
+            for (id, user) in looked_up {
+                cache.insert(id.clone(), (now, user.clone()));
+                found.insert(id, user);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Info about a user, from the cache if it was looked up less than the ttl ago.
+    pub async fn get(&self, id: &types::UserIdRef) -> Result<Option<UserInfo>, eyre::Report> {
+        Ok(self
+            .get_many(&[id])
+            .await?
+            .remove(id)
+            .flatten()
+            .map(|(_, info)| info))
+    }
+
+    /// Fill in the login and display name of users that only have their id set, in as few requests as possible.
+    /// Users that don't exist are left as is.
+    pub async fn resolve(&self, users: &mut [User]) -> Result<(), eyre::Report> {
+        let ids = users
+            .iter()
+            .map(|user| types::UserIdRef::from_str(&user.id))
+            .collect::<Vec<_>>();
+        let mut found = self.get_many(&ids).await?;
+        for user in users {
+            if let Some(Some((known, _))) = found.remove(types::UserIdRef::from_str(&user.id)) {
+                *user = known;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set [`ModerationEvent::target_info`] on bans, timeouts, warnings and deletes.
+    ///
+    /// Failing to look up the user is logged, the event is still posted.
+    pub async fn enrich(&self, event: &mut ModerationEvent) {
+        let Some(ref target) = event.target else {
+            return;
+        };
+        if !CHATTER_ACTIONS.contains(&event.kind.as_str()) {
+            return;
+        }
+        match self.get(types::UserIdRef::from_str(&target.id)).await {
+            Ok(info) => event.target_info = info,
+            Err(e) => tracing::warn!(error = ?e, user = target.login, "could not look up user"),
+        }
+    }
+}
+
+/// The ban of `user` in `channel`, or `None` if they aren't banned.
+pub async fn get_ban(
+    client: &HelixClient<'static, reqwest::Client>,
+    token: &Mutex<UserToken>,
+    channel: &types::UserIdRef,
+    user: &types::UserIdRef,
+) -> Result<Option<Ban>, eyre::Report> {
+    let users = [user];
+    let request = twitch_api::helix::moderation::GetBannedUsersRequest::broadcaster_id(channel)
+        .users(&users[..]);
+    let response = {
+        let token = token.lock().await;
+        client
+            .req_get(request, &*token)
+            .await
+            .wrap_err("could not get banned users")?
+    };
+    Ok(response.data.into_iter().next().map(|ban| Ban {
+        created_at: ban.created_at.as_str().to_owned(),
+        expires_at: ban.expires_at.map(|t| t.as_str().to_owned()),
+        moderator: User::new(&ban.moderator_id, &ban.moderator_login, &ban.moderator_name),
+        reason: ban.reason,
+    }))
+}
@@ -153,7 +153,7 @@ impl Visit for EventFieldVisitor {
     }
 }
 
-const MARKDOWN_TOKENS: &[char] = &['_', '*', '`', '~', '#', '+', '-'];
+const MARKDOWN_TOKENS: &[char] = &['_', '*', '`', '~', '#', '+', '-', '|'];
 
 pub trait Sanitize {
     fn sanitize(&self) -> Cow<'_, str>;
@@ -178,6 +178,18 @@ where
     }
 }
 
+/// The start of `s` that is at most `max` bytes long.
+pub fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
 impl<S, N> core::fmt::Display for FullCtx<'_, S, N>
 where
     S: Subscriber + for<'lookup> LookupSpan<'lookup>,
@@ -267,8 +279,221 @@ where
 }
 
 impl<S: Subscriber + for<'a> LookupSpan<'a>> tracing_subscriber::Layer<S> for Formatter {}
+
+/// Format of the log output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+    Logfmt,
+}
+
+/// When to rotate the log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRotation {
+    Never,
+    Daily,
+    /// Rotate when the file grows past this many bytes.
+    Size(u64),
+}
+
+impl std::str::FromStr for LogRotation {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" => return Ok(LogRotation::Never),
+            "daily" => return Ok(LogRotation::Daily),
+            _ => {}
+        }
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (size, unit) = s.split_at(split);
+        let size: u64 = size
+            .parse()
+            .wrap_err_with(|| format!("invalid log rotation `{s}`"))?;
+        let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" => 1024,
+            "M" | "MB" => 1024 * 1024,
+            "G" | "GB" => 1024 * 1024 * 1024,
+            _ => eyre::bail!("unknown size unit `{unit}`, expected one of B, KB, MB or GB"),
+        };
+        if size == 0 {
+            eyre::bail!("log rotation size must be larger than 0");
+        }
+        let Some(size) = size.checked_mul(multiplier) else {
+            eyre::bail!("log rotation size `{s}` is too large");
+        };
+        Ok(LogRotation::Size(size))
+    }
+}
+
+/// A file writer that rotates the file once it grows past a size.
+///
+/// Rotated files are named `<file>.1`, `<file>.2` and so on, where `<file>.1` is the most recent.
+struct SizeRotatingFile {
+    path: std::path::PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: std::fs::File,
+    written: u64,
+}
+
+impl SizeRotatingFile {
+    fn new(path: &std::path::Path, max_size: u64, max_files: usize) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            max_size,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> std::path::PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        use std::io::Write as _;
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = std::fs::File::create(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl std::io::Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Quote a value for logfmt if needed.
+///
+/// Values that are already quoted by their `Debug` implementation are left as is.
+fn logfmt_value(value: &str) -> Cow<'_, str> {
+    if value.is_empty() {
+        Cow::Borrowed("\"\"")
+    } else if value.starts_with('"') && value.ends_with('"') && value.len() > 1 {
+        Cow::Borrowed(value)
+    } else if value.contains(|c: char| c.is_whitespace() || c == '=' || c == '"') {
+        Cow::Owned(format!("{value:?}"))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Formats events as `key=value` pairs, see <https://brandur.org/logfmt>
+#[derive(Clone, Debug, PartialEq)]
+struct LogfmtFormatter;
+
+impl<S, N> FormatEvent<S, N> for LogfmtFormatter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer,
+        event: &tracing::Event<'_>,
+    ) -> core::fmt::Result {
+        let normalized_meta = event.normalized_metadata();
+        let event_meta = normalized_meta.as_ref().unwrap_or_else(|| event.metadata());
+        write!(writer, "ts=")?;
+        tracing_subscriber::fmt::time::SystemTime.format_time(&mut writer)?;
+        write!(
+            writer,
+            " level={} target={}",
+            event_meta.level().as_str().to_ascii_lowercase(),
+            logfmt_value(event_meta.target())
+        )?;
+
+        let span = event
+            .parent()
+            .and_then(|id| ctx.span(id))
+            .or_else(|| ctx.lookup_current());
+        let scope = span.into_iter().flat_map(|span| span.scope().from_root());
+        let mut names = vec![];
+        let mut fields = String::new();
+        for span in scope {
+            names.push(span.metadata().name());
+            let ext = span.extensions();
+            let span_fields = &ext
+                .get::<FormattedFields<N>>()
+                .expect("Unable to find FormattedFields in extensions; this is a bug");
+            if !span_fields.is_empty() {
+                fields.push(' ');
+                fields.push_str(span_fields);
+            }
+        }
+        if !names.is_empty() {
+            write!(writer, " span={}", logfmt_value(&names.join(">")))?;
+        }
+        let mut event_fields = String::new();
+        ctx.format_fields(Writer::new(&mut event_fields), event)?;
+        if !event_fields.is_empty() {
+            fields.push(' ');
+            fields.push_str(&event_fields);
+        }
+        writeln!(writer, "{fields}")
+    }
+}
+
+/// Formats fields as `key=value` separated by spaces, for [`LogfmtFormatter`].
+fn logfmt_fields() -> impl for<'w> FormatFields<'w> + Send + Sync + 'static {
+    use tracing_subscriber::prelude::__tracing_subscriber_field_MakeExt as _;
+
+    tracing_subscriber::fmt::format::debug_fn(|writer, field, value| {
+        write!(
+            writer,
+            "{}={}",
+            field.name(),
+            logfmt_value(&format!("{value:?}"))
+        )
+    })
+    .delimited(" ")
+}
+
 /// Build a logger that does file and term logging.
-pub fn build_logger() -> Result<(), eyre::Report> {
+///
+/// The returned guard flushes the log file when dropped and must be kept alive for the lifetime of the program.
+pub fn build_logger(
+    opts: &crate::Opts,
+) -> Result<tracing_appender::non_blocking::WorkerGuard, eyre::Report> {
     use tracing_subscriber::prelude::__tracing_subscriber_field_MakeExt as _;
 
     tracing_log::log_tracer::Builder::new()
@@ -285,20 +510,154 @@ pub fn build_logger() -> Result<(), eyre::Report> {
         .add_directive("tungstenite=info".parse()?)
         .add_directive("reqwest=info".parse()?)
         .add_directive("mio=off".parse()?);
-    let field_formatter = tracing_subscriber::fmt::format::debug_fn(|writer, field, value| {
-        write!(writer, "{}: {:?}", field.name().yellow().dimmed(), value)
-    })
-    // Use the `tracing_subscriber::MakeFmtExt` trait to wrap the
-    // formatter so that a delimiter is added between fields.
-    .delimited(", ");
 
-    let subscriber = tracing_subscriber::fmt::fmt()
+    let (writer, guard) = if let Some(ref path) = opts.log_file {
+        let directory = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| std::path::Path::new("."));
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| eyre::eyre!("log file `{}` is not a file", path.display()))?;
+        match opts.log_rotation {
+            LogRotation::Never => tracing_appender::non_blocking(tracing_appender::rolling::never(
+                directory, file_name,
+            )),
+            LogRotation::Daily => tracing_appender::non_blocking(
+                tracing_appender::rolling::Builder::new()
+                    .rotation(tracing_appender::rolling::Rotation::DAILY)
+                    .filename_prefix(file_name.to_string_lossy())
+                    .max_log_files(opts.log_max_files.max(1))
+                    .build(directory)
+                    .wrap_err("could not create log file")?,
+            ),
+            LogRotation::Size(max_size) => tracing_appender::non_blocking(
+                SizeRotatingFile::new(path, max_size, opts.log_max_files)
+                    .wrap_err_with(|| format!("could not open log file `{}`", path.display()))?,
+            ),
+        }
+    } else {
+        tracing_appender::non_blocking(std::io::stdout())
+    };
+
+    let builder = tracing_subscriber::fmt::fmt()
         .with_target(true)
         .with_env_filter(filter)
-        .event_format(Formatter)
-        .fmt_fields(field_formatter)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)
-        .wrap_err("could not set global tracing logger")?;
-    Ok(())
+        .with_writer(writer);
+    match opts.log_format {
+        LogFormat::Pretty => {
+            let field_formatter =
+                tracing_subscriber::fmt::format::debug_fn(|writer, field, value| {
+                    write!(writer, "{}: {:?}", field.name().yellow().dimmed(), value)
+                })
+                // Use the `tracing_subscriber::MakeFmtExt` trait to wrap the
+                // formatter so that a delimiter is added between fields.
+                .delimited(", ");
+            tracing::subscriber::set_global_default(
+                builder
+                    .event_format(Formatter)
+                    .fmt_fields(field_formatter)
+                    .finish(),
+            )
+        }
+        LogFormat::Json => tracing::subscriber::set_global_default(
+            builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(true)
+                .finish(),
+        ),
+        LogFormat::Logfmt => tracing::subscriber::set_global_default(
+            builder
+                .event_format(LogfmtFormatter)
+                .fmt_fields(logfmt_fields())
+                .finish(),
+        ),
+    }
+    .wrap_err("could not set global tracing logger")?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn parses_log_rotation() {
+        for (s, rotation) in [
+            ("never", LogRotation::Never),
+            ("Daily", LogRotation::Daily),
+            ("512", LogRotation::Size(512)),
+            ("10 KB", LogRotation::Size(10 * 1024)),
+            ("10mb", LogRotation::Size(10 * 1024 * 1024)),
+            ("1G", LogRotation::Size(1024 * 1024 * 1024)),
+        ] {
+            assert_eq!(s.parse::<LogRotation>().unwrap(), rotation, "{s}");
+        }
+        for s in ["", "0MB", "10TB", "MB", "-1", "99999999999GB"] {
+            assert!(s.parse::<LogRotation>().is_err(), "{s}");
+        }
+    }
+
+    /// Collects what a logger writes.
+    #[derive(Clone, Default)]
+    struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn formats_logfmt() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt::fmt()
+            .with_writer(move || writer.clone())
+            .event_format(LogfmtFormatter)
+            .fmt_fields(logfmt_fields())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("sink", sink = "webhook").entered();
+            tracing::info!(kind = "ban", "event sent");
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let (ts, line) = output.split_once(' ').unwrap();
+        assert!(ts.starts_with("ts="), "{output}");
+        assert_eq!(
+            line,
+            "level=info target=twitch_discord_moderation::util::tests span=sink sink=\"webhook\" message=\"event sent\" kind=\"ban\"\n"
+        );
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("size-rotating-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bot.log");
+        let mut file = SizeRotatingFile::new(&path, 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let files = (read("bot.log"), read("bot.log.1"), read("bot.log.2"));
+        let extra = dir.join("bot.log.3").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            files,
+            ("fourth\n".into(), "third\n".into(), "second\n".into())
+        );
+        assert!(!extra);
+    }
 }
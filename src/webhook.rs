@@ -1,313 +1,570 @@
-use crate::util::Sanitize;
-use tokio::sync;
-use twitch_api::{
-    eventsub::channel::moderate::{self, ActionV2},
-    types,
-};
+use crate::event::ModerationEvent;
+use crate::filter::{Digest, Filter, Then};
+use crate::routing::{Destination, Route};
+use eyre::WrapErr;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use twitch_api::types;
+
+/// Discord messages can't be longer than this
+pub(crate) const MAX_MESSAGE_LEN: usize = 2000;
+
+/// Posts to remember for editing at most. When there are more, the oldest is forgotten.
+const MAX_TRACKED: usize = 10_000;
+
+/// A message posted to a destination.
+#[derive(Clone, Debug)]
+struct Post {
+    destination: Destination,
+    message_id: serenity::all::MessageId,
+}
+
+/// Messages posted about something that can change later, e.g an unban request that is resolved.
+struct Tracked {
+    at: Instant,
+    text: String,
+    posts: Vec<Post>,
+    /// When a timeout ends
+    expires: Option<time::OffsetDateTime>,
+}
+
 pub struct Webhook {
-    pub webhook: serenity::model::webhook::Webhook,
-    pub channel_login: types::UserName,
+    /// Where events go that don't match a route, `--discord-webhook`
+    default: Destination,
+    routes: Vec<Route>,
+    filters: Vec<Filter>,
+    /// Events collected for filters with `then = "digest"`, by index of the filter
+    digests: std::sync::Mutex<HashMap<usize, Digest>>,
+    /// Webhooks of all destinations, by url
+    webhooks: HashMap<crate::Secret, serenity::model::webhook::Webhook>,
+    /// Posts to edit later, by what they are about, e.g `unban_request:<id>` or `warn:<channel id>:<user id>`
+    tracked: std::sync::Mutex<HashMap<String, Tracked>>,
     pub channel_bot_name: Option<types::DisplayName>,
+    /// Always attach chat context as a file, `--chat-context-file`
+    chat_context_file: bool,
+    /// Edit timeout posts when they end, `--mark-expired-timeouts`
+    mark_expired_timeouts: bool,
+    pub health: Arc<crate::health::Health>,
     discord_http: serenity::http::Http,
 }
 
 impl Webhook {
-    fn add_streamcardlink(&self, user_login: &str) -> String {
-        format!(
-            "[{1}](<https://www.twitch.tv/popout/{0}/viewercard/{1}?popout=>)",
-            self.channel_login.sanitize(),
-            user_login
-        )
-    }
-
     pub async fn new(
         client: &reqwest::Client,
-        channel_login: types::UserName,
         opts: &crate::Opts,
+        health: Arc<crate::health::Health>,
     ) -> Result<Webhook, eyre::Report> {
-        let http = serenity::http::HttpBuilder::without_token()
+        let mut http = serenity::http::HttpBuilder::without_token()
             .client(client.clone())
             .build();
-        Ok(Webhook {
-            webhook: serenity::model::webhook::Webhook::from_url(
-                &http,
-                opts.discord_webhook.as_str(),
-            )
-            .await?,
-            channel_login,
-            channel_bot_name: opts.channel_bot_name.clone().map(types::DisplayName::new),
+        if let Some(ref mut ratelimiter) = http.ratelimiter {
+            ratelimiter.set_ratelimit_callback(Box::new(|_| {
+                crate::metrics::DISCORD_RATE_LIMITS.inc();
+            }));
+        }
+        let mut webhook = Webhook {
+            default: Destination {
+                webhook: opts.discord_webhook().clone(),
+                thread_id: None,
+            },
+            routes: vec![],
+            filters: vec![],
+            digests: Default::default(),
+            webhooks: HashMap::new(),
+            tracked: Default::default(),
+            channel_bot_name: None,
+            chat_context_file: false,
+            mark_expired_timeouts: false,
+            health,
             discord_http: http,
-        })
+        };
+        webhook.update(opts).await?;
+        Ok(webhook)
     }
 
-    #[tracing::instrument(name = "webhook", skip(self, recv))]
-    pub async fn run(
-        &self,
-        mut recv: sync::broadcast::Receiver<crate::subscriber::Events>,
-    ) -> Result<(), eyre::Report> {
-        while let Ok(msg) = recv.recv().await {
-            tracing::info!("Received event {:?}", msg);
-            match msg {
-                crate::subscriber::Events::ChannelModerateV2(p, t) => {
-                    self.post_moderator_action(p.action, p.moderator_user_login, t)
-                        .await?
+    /// Apply reloaded options. If a new webhook doesn't work, nothing is changed.
+    pub async fn update(&mut self, opts: &crate::Opts) -> Result<(), eyre::Report> {
+        let default = Destination {
+            webhook: opts.discord_webhook().clone(),
+            thread_id: None,
+        };
+        let routes = opts.config_file.routes.clone();
+        let filters = opts.config_file.filters.clone();
+        let mut webhooks = HashMap::new();
+        for url in std::iter::once(&default)
+            .chain(routes.iter().flat_map(|r| &r.to))
+            .chain(filters.iter().flat_map(|f| &f.to))
+            .map(|d| &d.webhook)
+        {
+            if webhooks.contains_key(url) {
+                continue;
+            }
+            let webhook = match self.webhooks.get(url) {
+                Some(webhook) => webhook.clone(),
+                None => {
+                    serenity::model::webhook::Webhook::from_url(&self.discord_http, url.secret())
+                        .await
+                        .wrap_err("could not get webhook")?
+                }
+            };
+            webhooks.insert(url.clone(), webhook);
+        }
+        // digests are kept by index of the filter, which might change. Digests of filters that are gone are posted now
+        let removed = {
+            let mut digests = self.digests.lock().unwrap();
+            let mut kept = HashMap::new();
+            let mut removed = vec![];
+            for (i, digest) in digests.drain() {
+                let moved = self.filters.get(i).and_then(|filter| {
+                    (0..filters.len()).find(|n| filters[*n] == *filter && !kept.contains_key(n))
+                });
+                match moved {
+                    Some(n) => {
+                        kept.insert(n, digest);
+                    }
+                    None => removed.push((i, digest)),
                 }
             }
+            *digests = kept;
+            removed
+        };
+        for (i, digest) in removed {
+            let destinations = self.filter_destinations(self.filters.get(i));
+            if let Err(e) = self
+                .post(&destinations, &digest.render(), "twitch_moderation", None)
+                .await
+            {
+                tracing::warn!(error = ?e, "could not post the digest of a removed filter");
+            }
         }
+        self.default = default;
+        self.routes = routes;
+        self.filters = filters;
+        self.webhooks = webhooks;
+        self.channel_bot_name = opts.channel_bot_name.clone().map(types::DisplayName::new);
+        self.chat_context_file = opts.chat_context_file;
+        self.mark_expired_timeouts = opts.mark_expired_timeouts;
         Ok(())
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn post_moderator_action(
-        &self,
-        action: ActionV2,
-        moderator: types::UserName,
-        timestamp: types::Timestamp,
-    ) -> Result<(), eyre::Report> {
-        let mut message = None;
-        let done_by = format!("{}@twitch", moderator,);
-        match action {
-            // translation of the old commented code to more modern code and using eventsub instead of pubsub
-            ActionV2::Delete(moderate::Delete {
-                user_id,
-                user_login,
-                message_body,
-                ..
-            }) => {
-                message = Some(format!(
-                        "❌_Twitch Moderation_ |\n*{moderator}*: /delete {usercard} ||{message_body}||\n*{usercard}:{user_id}* message deleted",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                        message_body = message_body.sanitize(),
-                    ));
-            }
-            ActionV2::Timeout(moderate::Timeout {
-                user_id,
-                user_login,
-                expires_at,
-                reason,
-                ..
-            }) => {
-                // eventsub gives expires_at as a timestamp, so we need to calculate the duration
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /timeout {usercard}\n*{usercard}:{user_id}* has been timed out until <t:{expires}>{reason}",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                        expires = expires_at.to_utc().unix_timestamp(),
-                        reason = if let Some(reason) = reason {
-                            format!("\nreason: {}", reason.sanitize())
-                        } else {
-                            "".to_string()
-                        },
-                    ));
-            }
-            ActionV2::Untimeout(moderate::Untimeout {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /untimeout {usercard}\n*{usercard}:{user_id}* is no longer timed out",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
-            }
-            ActionV2::Ban(moderate::Ban {
-                user_id,
-                user_login,
-                reason,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🏝️_Twitch Moderation_ |\n*{moderator}*: /ban {usercard}\n*{usercard}:{user_id}* is now banned{reason}",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                        reason = if let Some(reason) = reason {
-                            format!("\nreason: {}", reason.sanitize())
-                        } else {
-                            "".to_string()
-                        },
-                    ));
-            }
-            ActionV2::Unban(moderate::Unban {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🏝️_Twitch Moderation_ |\n*{moderator}*: /unban {usercard}\n*{usercard}:{user_id}* is no longer banned",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
-            }
-            ActionV2::Followers(moderate::Followers {
-                follow_duration_minutes,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔒_Twitch Moderation_ |\n*{moderator}*: /followers {follow_duration_minutes}m\nFollowers-only mode is now enabled for {follow_duration_minutes} minutes",
-                    ));
+    /// Time until the next digest should be posted.
+    fn next_digest(&self) -> Option<Duration> {
+        self.digests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(i, digest)| {
+                self.filters
+                    .get(*i)
+                    .map_or(Duration::ZERO, |f| f.digest_interval)
+                    .saturating_sub(digest.since.elapsed())
+            })
+            .min()
+    }
+
+    /// Time until the next tracked timeout ends, with `--mark-expired-timeouts`.
+    fn next_expiry(&self) -> Option<Duration> {
+        if !self.mark_expired_timeouts {
+            return None;
+        }
+        let now = time::OffsetDateTime::now_utc();
+        self.tracked
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|t| t.expires)
+            .min()
+            .map(|expires| Duration::try_from(expires - now).unwrap_or(Duration::ZERO))
+    }
+
+    /// Edit the posts of timeouts that have ended to say so.
+    async fn mark_expired(&self) {
+        if !self.mark_expired_timeouts {
+            return;
+        }
+        let now = time::OffsetDateTime::now_utc();
+        let expired = {
+            let mut tracked = self.tracked.lock().unwrap();
+            let keys = tracked
+                .iter()
+                .filter(|(_, t)| t.expires.is_some_and(|e| e <= now))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            keys.into_iter()
+                .filter_map(|key| tracked.remove(&key))
+                .collect::<Vec<_>>()
+        };
+        for tracked in expired {
+            let expires = tracked.expires.map_or(0, |e| e.unix_timestamp());
+            let text = append(&tracked.text, &format!("\ntimeout expired <t:{expires}:R>"));
+            self.edit(&tracked.posts, &text).await;
+        }
+    }
+
+    /// Post the digests that are due, or all of them.
+    async fn flush_digests(&self, all: bool) -> Result<(), eyre::Report> {
+        let due = {
+            let mut digests = self.digests.lock().unwrap();
+            let due = digests
+                .iter()
+                .filter(|(i, digest)| {
+                    all || self
+                        .filters
+                        .get(**i)
+                        .is_none_or(|f| digest.since.elapsed() >= f.digest_interval)
+                })
+                .map(|(i, _)| *i)
+                .collect::<Vec<_>>();
+            due.into_iter()
+                .filter_map(|i| digests.remove(&i).map(|d| (i, d)))
+                .collect::<Vec<_>>()
+        };
+        for (i, digest) in due {
+            let destinations = self.filter_destinations(self.filters.get(i));
+            self.post(&destinations, &digest.render(), "twitch_moderation", None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Destinations of a filter, or `--discord-webhook` if it has none.
+    fn filter_destinations<'a>(&'a self, filter: Option<&'a Filter>) -> Vec<&'a Destination> {
+        match filter {
+            Some(filter) if !filter.to.is_empty() => filter.to.iter().collect(),
+            _ => vec![&self.default],
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(kind = %event.kind, channel = %event.channel.login))]
+    pub async fn post_moderator_action(&self, event: &ModerationEvent) -> Result<(), eyre::Report> {
+        let done_by = match event.moderator {
+            Some(ref moderator) => format!("{}@twitch", moderator.login),
+            None => "twitch".to_owned(),
+        };
+        let kind = event.kind.as_str();
+        let subject = event.subject();
+        let filter = crate::filter::find(
+            &self.filters,
+            &subject,
+            self.channel_bot_name.as_ref().map(|n| n.as_str()),
+        );
+        let destinations = match filter {
+            Some((_, filter)) if filter.then == Then::Drop => {
+                tracing::debug!(kind, "action dropped by filter");
+                return Ok(());
             }
-            ActionV2::Slow(moderate::Slow {
-                wait_time_seconds, ..
-            }) => {
-                message = Some(format!(
-                        "🔒_Twitch Moderation_ |\n*{moderator}*: /slow {wait_time_seconds}s\nSlow mode is now enabled with {wait_time_seconds} seconds",
-                    ));
+            Some((_, filter)) => self.filter_destinations(Some(filter)),
+            None => crate::routing::destinations(&self.routes, &self.default, &subject),
+        };
+        let digest = filter
+            .filter(|(_, f)| f.then == Then::Digest)
+            .map(|(i, _)| i);
+        let message = crate::render::moderator_action(event, crate::render::Markup::Discord);
+        if let Some(text) = message {
+            if kind == "unban_request_resolve" {
+                let key = format!(
+                    "unban_request:{}",
+                    event.unban_request_id.as_deref().unwrap_or_default()
+                );
+                if let Some(tracked) = self.untrack(&key) {
+                    // show the resolution below the request, without the header
+                    let resolution = text.split_once('\n').map_or(text.as_str(), |(_, r)| r);
+                    self.edit(
+                        &tracked.posts,
+                        &append(&tracked.text, &format!("\n{resolution}")),
+                    )
+                    .await;
+                    return Ok(());
+                }
             }
-            ActionV2::Vip(moderate::Vip {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /vip {usercard}\n*{usercard}:{user_id}* is now a VIP",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+            if kind == "warning_acknowledge" {
+                if let Some(tracked) = target_key("warn", event).and_then(|key| self.untrack(&key))
+                {
+                    let acknowledged = format!(
+                        "\nacknowledged {}",
+                        crate::render::Markup::Discord.time_ago(&event.timestamp)
+                    );
+                    self.edit(&tracked.posts, &append(&tracked.text, &acknowledged))
+                        .await;
+                    return Ok(());
+                }
             }
-            ActionV2::Unvip(moderate::Unvip {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /unvip {usercard}\n*{usercard}:{user_id}* is no longer a VIP",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+            if let ("untimeout" | "unban", Some(key)) = (kind, target_key("ban", event)) {
+                // webhooks can't reply, the original post says that it was lifted instead
+                if let Some(tracked) = self.untrack(&key) {
+                    self.edit(&tracked.posts, &lifted(&tracked.text, event))
+                        .await;
+                    return Ok(());
+                }
             }
-            ActionV2::Mod(moderate::Mod {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /mod {usercard}\n*{usercard}:{user_id}* is now a moderator",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+            if let Some(i) = digest {
+                // only keep the line with the command, e.g `*mod*: /delete user ||message||`
+                let line = text.lines().nth(1).unwrap_or(&text).to_owned();
+                self.digests
+                    .lock()
+                    .unwrap()
+                    .entry(i)
+                    .or_insert_with(|| Digest {
+                        since: Instant::now(),
+                        lines: vec![],
+                    })
+                    .lines
+                    .push(line);
+                return Ok(());
             }
-            ActionV2::Unmod(moderate::Unmod {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /unmod {usercard}\n*{usercard}:{user_id}* is no longer a moderator",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+            // the context goes in the post if it fits, otherwise in a file
+            let context = crate::render::chat_context(event, crate::render::Markup::Discord);
+            let (text, posts) = match context {
+                Some(context)
+                    if !self.chat_context_file
+                        && text.len() + 1 + context.len() <= MAX_MESSAGE_LEN =>
+                {
+                    let text = format!("{text}\n{context}");
+                    let posts = self.post(&destinations, &text, &done_by, None).await?;
+                    (text, posts)
+                }
+                Some(_) => {
+                    let file = crate::render::chat_context(event, crate::render::Markup::Plain);
+                    let posts = self
+                        .post(&destinations, &text, &done_by, file.as_deref())
+                        .await?;
+                    (text, posts)
+                }
+                None => {
+                    let posts = self.post(&destinations, &text, &done_by, None).await?;
+                    (text, posts)
+                }
+            };
+            let key = match kind {
+                "unban_request" => event
+                    .unban_request_id
+                    .as_ref()
+                    .map(|id| format!("unban_request:{id}")),
+                "warn" => target_key("warn", event),
+                "ban" | "timeout" => target_key("ban", event),
+                _ => None,
+            };
+            if let Some(key) = key {
+                // only timeouts expire
+                let expires = event
+                    .expires_at
+                    .as_ref()
+                    .and_then(|e| types::Timestamp::new(e.clone()).ok())
+                    .map(|e| e.to_utc());
+                self.track(key, text, posts, expires);
             }
-            ActionV2::Raid(moderate::Raid {
-                user_id,
-                user_login,
-                viewer_count,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /raid {usercard} {viewer_count}\n*{usercard}:{user_id}* is now being raided",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+        }
+        Ok(())
+    }
+
+    /// Remember posts to edit them later.
+    fn track(
+        &self,
+        key: String,
+        text: String,
+        posts: Vec<Post>,
+        expires: Option<time::OffsetDateTime>,
+    ) {
+        let mut tracked = self.tracked.lock().unwrap();
+        if !tracked.contains_key(&key) && tracked.len() >= MAX_TRACKED {
+            let oldest = tracked
+                .iter()
+                .min_by_key(|(_, t)| t.at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                tracked.remove(&oldest);
             }
-            ActionV2::Unraid(moderate::Unraid {
-                user_id,
-                user_login,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /unraid {usercard}\n*{usercard}:{user_id}* raid was canceled",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                    ));
+        }
+        tracked.insert(
+            key,
+            Tracked {
+                at: Instant::now(),
+                text,
+                posts,
+                expires,
+            },
+        );
+    }
+
+    /// Forget posts, returning them if they were remembered.
+    fn untrack(&self, key: &str) -> Option<Tracked> {
+        self.tracked.lock().unwrap().remove(key)
+    }
+
+    /// Change the text of posts. Failing is logged, the message might have been deleted.
+    async fn edit(&self, posts: &[Post], text: &str) {
+        for post in posts {
+            let Some(webhook) = self.webhooks.get(&post.destination.webhook) else {
+                // the destination was removed from the config since
+                continue;
+            };
+            let mut builder = serenity::all::EditWebhookMessage::new()
+                .content(text)
+                .allowed_mentions(serenity::all::CreateAllowedMentions::new());
+            if let Some(thread_id) = post.destination.thread_id {
+                builder = builder.in_thread(thread_id);
             }
-            ActionV2::ApproveUnbanRequest(moderate::UnbanRequest {
-                user_id,
-                user_login,
-                moderator_message,
-                ..
-            }) => {
-                message = Some(format!(
-                    "🔨_Twitch Moderation_ |\n*{moderator}*: /approve {usercard} : {moderator_message}\n*{usercard}:{user_id}* unban was approved",
-                    usercard = self.add_streamcardlink(user_login.as_str()),
-                    moderator_message = moderator_message.sanitize(),
-                ));
+            let result = webhook
+                .edit_message(&self.discord_http, post.message_id, builder)
+                .await;
+            crate::metrics::record_delivery(&result);
+            if let Err(e) = result {
+                tracing::warn!(error = ?e, message_id = %post.message_id, "could not edit post");
             }
-            ActionV2::DenyUnbanRequest(moderate::UnbanRequest {
-                user_id,
-                user_login,
-                moderator_message,
-                ..
-            }) => {
-                message = Some(format!(
-                    "🔨_Twitch Moderation_ |\n*{moderator}*: /deny {usercard} : {moderator_message}\n*{usercard}:{user_id}* unban was denied",
-                    usercard = self.add_streamcardlink(user_login.as_str()),
-                    moderator_message = moderator_message.sanitize(),
-                ));
+        }
+    }
+
+    /// Post a message to every destination. Fails with the first error after trying all of them.
+    async fn post(
+        &self,
+        destinations: &[&Destination],
+        text: &str,
+        username: &str,
+        attachment: Option<&str>,
+    ) -> Result<Vec<Post>, eyre::Report> {
+        let mut posts = vec![];
+        let mut error = None;
+        for destination in destinations {
+            let Some(webhook) = self.webhooks.get(&destination.webhook) else {
+                // all destinations are resolved in update()
+                continue;
+            };
+            // chat messages and reasons are written by users, don't let them ping anyone
+            let mut builder = serenity::all::ExecuteWebhook::new()
+                .content(text)
+                .username(username)
+                .allowed_mentions(serenity::all::CreateAllowedMentions::new());
+            if let Some(thread_id) = destination.thread_id {
+                builder = builder.in_thread(thread_id);
             }
-            ActionV2::SharedChatBan(moderate::SharedChatBan(_))
-            | ActionV2::SharedChatUnban(moderate::SharedChatUnban(_))
-            | ActionV2::SharedChatTimeout(moderate::SharedChatTimeout(_))
-            | ActionV2::SharedChatUntimeout(moderate::SharedChatUntimeout(_))
-            | ActionV2::SharedChatDelete(moderate::SharedChatDelete(_)) => {
-                // NOP
-            },
-            ActionV2::EmoteOnly => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /emoteonly\nEmote-only mode is now enabled")),
-            ActionV2::EmoteOnlyOff => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /emoteonlyoff\nEmote-only mode is now disabled")),
-            ActionV2::FollowersOff => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /followersoff\nFollowers-only mode is now disabled")),
-            ActionV2::Uniquechat => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /uniquechat\nUnique chat is now enabled")),
-            ActionV2::UniquechatOff => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /uniquechatoff\nUnique chat is now disabled")),
-            ActionV2::SlowOff => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /slowoff\nSlow mode is now disabled")),
-            ActionV2::Subscribers => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /subscribers\nSubscribers-only mode is now enabled")),
-            ActionV2::SubscribersOff => message = Some(format!("🔒_Twitch Moderation_ |\n*{moderator}*: /subscribersoff\nSubscribers-only mode is now disabled")),
-            ActionV2::AddBlockedTerm(terms)
-            | ActionV2::AddPermittedTerm(terms)
-            | ActionV2::RemoveBlockedTerm(terms)
-            | ActionV2::RemovePermittedTerm(terms) => {
-                // either add or remove
-                let action = match (terms.action, terms.from_automod, terms.list) {
-                    (moderate::AutomodTermAction::Add, true, moderate::AutomodTermList::Blocked) => "temp_term_add_block".to_owned(),
-                    (moderate::AutomodTermAction::Add, true, moderate::AutomodTermList::Permitted) => "temp_term_add_permit".to_owned(),
-                    (moderate::AutomodTermAction::Add, false, moderate::AutomodTermList::Blocked) => "term_add_block".to_owned(),
-                    (moderate::AutomodTermAction::Add, false, moderate::AutomodTermList::Permitted) => "term_add_permit".to_owned(),
-                    (moderate::AutomodTermAction::Remove, true, moderate::AutomodTermList::Blocked) => "temp_term_remove_block".to_owned(),
-                    (moderate::AutomodTermAction::Remove, true, moderate::AutomodTermList::Permitted) => "temp_term_remove_permit".to_owned(),
-                    (moderate::AutomodTermAction::Remove, false, moderate::AutomodTermList::Blocked) => "term_remove_block".to_owned(),
-                    (moderate::AutomodTermAction::Remove, false, moderate::AutomodTermList::Permitted) => "term_remove_permit".to_owned(),
-                    (a, from_automod, list) => format!("unknown_{a:?}_{from_automod}_{list:?}",),
-                };
-                message = Some(format!(
-                    "🔨_Twitch Moderation_ |\n*{moderator}*: /{action} {terms}\nTerms {action}ed{temp}: {terms}",
-                    action = action,
-                    temp = if terms.from_automod { " temporarily" } else { "" },
-                    terms = terms.terms.join(", "),
+            if let Some(attachment) = attachment {
+                builder = builder.add_file(serenity::all::CreateAttachment::bytes(
+                    attachment.as_bytes(),
+                    "chat.txt",
                 ));
-
             }
-            ActionV2::Warn(moderate::Warn {
-                user_id,
-                user_login,
-                reason,
-                chat_rules_cited, // Option<Vec<String>>,
-                ..
-            }) => {
-                message = Some(format!(
-                        "🔨_Twitch Moderation_ |\n*{moderator}*: /warn {usercard}\n*{usercard}:{user_id}* has been warned{chat_rules_cited}{reason}",
-                        usercard = self.add_streamcardlink(user_login.as_str()),
-                        chat_rules_cited = if let Some(rules) = chat_rules_cited {
-                            format!(" for breaking rules: {}", rules.join(", "))
-                        } else {
-                            "".to_string()
-                        },
-                        reason = if let Some(reason) = reason {
-                            format!("\nreason: {}", reason.sanitize())
-                        } else {
-                            "".to_string()
-                        },
-                    ));
-            },
-            _ => {
-                tracing::warn!("Unknown action {:?}", action);
+            // wait for the message, to be able to edit it
+            let result = webhook.execute(&self.discord_http, true, builder).await;
+            self.health.record_post(result.is_ok());
+            crate::metrics::record_delivery(&result);
+            match result {
+                Ok(Some(message)) => posts.push(Post {
+                    destination: (*destination).clone(),
+                    message_id: message.id,
+                }),
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
             }
         }
-        if let Some(text) = message {
-            let builder = serenity::all::ExecuteWebhook::new()
-                .content(&text)
-                .username(&done_by);
-            self.webhook
-                .execute(&self.discord_http, false, builder)
-                .await?;
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(posts),
         }
-        Ok(())
+    }
+}
+
+impl crate::sink::Sink for Webhook {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn send(&self, event: &ModerationEvent) -> Result<(), eyre::Report> {
+        // the full event has chat messages and user info in it, keep those out of the default log
+        tracing::info!(
+            kind = %event.kind,
+            channel_id = %event.channel.id,
+            target_id = ?event.target.as_ref().map(|t| &t.id),
+            "received event"
+        );
+        tracing::debug!(?event, "received event");
+        self.post_moderator_action(event).await
+    }
+
+    async fn update(&mut self, opts: &crate::Opts) -> Result<(), eyre::Report> {
+        Webhook::update(self, opts).await
+    }
+
+    fn next_tick(&self) -> Option<Duration> {
+        [self.next_digest(), self.next_expiry()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    async fn tick(&self, shutdown: bool) -> Result<(), eyre::Report> {
+        self.mark_expired().await;
+        self.flush_digests(shutdown).await
+    }
+}
+
+/// Key of the posts about the target of an event, e.g `ban:<channel id>:<user id>` for bans and timeouts.
+fn target_key(prefix: &str, event: &ModerationEvent) -> Option<String> {
+    let target = event.target.as_ref()?;
+    Some(format!("{prefix}:{}:{}", event.channel.id, target.id))
+}
+
+/// A ban or timeout post with the action struck through, saying who lifted it.
+fn lifted(text: &str, event: &ModerationEvent) -> String {
+    let markup = crate::render::Markup::Discord;
+    // keep the header, target info and chat context as they are
+    let (header, body) = text.split_once('\n').unwrap_or(("", text));
+    let (action, rest) = body.split_once('\n').unwrap_or((body, ""));
+    let body = match rest {
+        "" => format!("~~{action}~~"),
+        rest => format!("~~{action}~~\n{rest}"),
+    };
+    let by = match event.moderator {
+        Some(ref moderator) => format!(" by {}", markup.emphasis(&moderator.login)),
+        None => "".to_owned(),
+    };
+    append(
+        &format!("{header}\n{body}"),
+        &format!("\nlifted{by} {}", markup.time_ago(&event.timestamp)),
+    )
+}
+
+/// `text` followed by `suffix`, cutting `text` short to fit in a message.
+fn append(text: &str, suffix: &str) -> String {
+    if text.len() + suffix.len() <= MAX_MESSAGE_LEN {
+        return format!("{text}{suffix}");
+    }
+    let suffix = crate::util::truncate(suffix, MAX_MESSAGE_LEN / 2);
+    let text = crate::util::truncate(text, MAX_MESSAGE_LEN - suffix.len() - '…'.len_utf8());
+    format!("{text}…{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::user;
+
+    #[test]
+    fn strikes_through_lifted_posts() {
+        let timestamp = types::Timestamp::new("2024-01-01T00:05:00Z".to_owned()).unwrap();
+        let event = ModerationEvent::new(
+            "untimeout",
+            &timestamp,
+            user("1", "twitch"),
+            Some(user("1", "justintv")),
+        );
+        assert_eq!(
+            lifted(
+                "🔨_Twitch Moderation_ |\n*justintv*: /timeout someone\n*someone:2* has been timed out",
+                &event
+            ),
+            "🔨_Twitch Moderation_ |\n~~*justintv*: /timeout someone~~\n*someone:2* has been timed out\nlifted by *justintv* <t:1704067500:R>"
+        );
+    }
+
+    #[test]
+    fn edits_fit_in_a_message() {
+        let text = "ä".repeat(MAX_MESSAGE_LEN);
+        let edited = append(&text, "\nacknowledged");
+        assert!(edited.len() <= MAX_MESSAGE_LEN);
+        assert!(edited.ends_with("ä…\nacknowledged"));
+        assert_eq!(append("short", "\nacknowledged"), "short\nacknowledged");
     }
 }